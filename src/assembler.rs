@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::num::IntErrorKind;
+use std::path::{Path, PathBuf};
+use crate::cpu::{CHIP8_RAM, PROGRAM_START};
+use crate::instruction::Instruction;
+
+// assembles Cowgod style mnemonics, the same syntax the disassembler prints. a line is an optional
+// `label:` followed by an instruction or directive, with `;` starting a comment:
+//
+//   SPEED equ 4            ; a constant
+//   start:  LD V0, SPEED
+//           LD I, sprite
+//           DRW V0, V1, 2
+//           JP start
+//   sprite: db 0xFF, 0x81
+//           include "other.asm"
+//
+// operands are registers or expressions adding and subtracting numbers (decimal, 0x hex or 0b
+// binary), labels and constants
+
+// names that can't be used for labels or constants
+const KEYWORDS: [&str; 13] = ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG", "EQU", "INCLUDE", "DB", "DW"];
+
+const MNEMONICS: [&str; 31] = [
+    "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE", "SAVE",
+    "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP",
+    "PLANE", "AUDIO", "PITCH",
+];
+
+// an error in the source, pointing at the file, line and column it was found at
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Clone, Debug)]
+struct Location {
+    file: String,
+    line: usize,
+    column: usize,
+}
+
+impl Location {
+    fn error(&self, message: String) -> AsmError {
+        AsmError { file: self.file.clone(), line: self.line, column: self.column, message }
+    }
+
+    // the location of a piece of text found further along the same line
+    fn offset(&self, by: usize) -> Location {
+        Location { column: self.column + by, ..self.clone() }
+    }
+}
+
+// labels are known once the first pass has laid out the program, constants are evaluated when they
+// are used so they can refer to labels and constants defined after them
+enum Symbol {
+    Label(usize),
+    Constant(String, Location),
+}
+
+// an instruction or data directive along with its operands, encoded once every symbol is known
+struct Statement {
+    location: Location,
+    addr: usize,
+    mnemonic: String,
+    operands: Vec<(String, Location)>,
+}
+
+enum Operand<'a> {
+    Register(u8),
+    Range(u8, u8),
+    // one of the fixed operands such as I, [I] or DT
+    Keyword(&'static str),
+    Long(&'a str, Location),
+    Value(&'a str, &'a Location),
+}
+
+// assemble source code into a ROM image to be loaded at PROGRAM_START. the name is used in errors and
+// includes are found relative to it
+pub fn assemble(source: &str, name: &str) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler {
+        statements: Vec::new(),
+        symbols: HashMap::new(),
+        addr: PROGRAM_START,
+        includes: vec![(PathBuf::from(name), canonical(Path::new(name)))],
+    };
+    assembler.parse(source, name)?;
+    assembler.encode()
+}
+
+struct Assembler {
+    statements: Vec<Statement>,
+    symbols: HashMap<String, Symbol>,
+    addr: usize,
+    // the files currently being parsed as they were named and with their real path, used to find
+    // includes relative to the file and to catch files that include themselves
+    includes: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Assembler {
+    // first pass, split the source into statements and give every label its address
+    fn parse(&mut self, source: &str, name: &str) -> Result<(), AsmError> {
+        for (number, line) in source.lines().enumerate() {
+            let code = strip_comment(line);
+            let column = |text: &str| text.as_ptr() as usize - line.as_ptr() as usize + 1;
+            let location = |text: &str| Location { file: name.to_string(), line: number + 1, column: column(text) };
+
+            let mut rest = code.trim();
+            if rest.is_empty() {
+                continue;
+            }
+            let (word, after) = split_word(rest);
+            if let Some(label) = word.strip_suffix(':') {
+                self.define(label, Symbol::Label(self.addr), location(word))?;
+                rest = after;
+                if rest.is_empty() {
+                    continue;
+                }
+            }
+
+            let (mnemonic, operands) = split_word(rest);
+            let (second, value) = split_word(operands);
+            if second.eq_ignore_ascii_case("equ") {
+                if value.is_empty() {
+                    return Err(location(second).error("expected a value after equ".to_string()));
+                }
+                self.define(mnemonic, Symbol::Constant(value.to_string(), location(value)), location(mnemonic))?;
+                continue;
+            }
+            if mnemonic.eq_ignore_ascii_case("include") {
+                self.include(operands, location(operands))?;
+                continue;
+            }
+
+            let operands: Vec<(String, Location)> = if operands.is_empty() {
+                Vec::new()
+            } else {
+                operands.split(',').map(|operand| (operand.trim().to_string(), location(operand.trim()))).collect()
+            };
+            let statement = Statement {
+                location: location(mnemonic),
+                addr: self.addr,
+                mnemonic: mnemonic.to_ascii_uppercase(),
+                operands,
+            };
+            self.addr += statement.size();
+            if self.addr > CHIP8_RAM {
+                return Err(statement.location.error("program is too large to fit in memory".to_string()));
+            }
+            self.statements.push(statement);
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, operand: &str, location: Location) -> Result<(), AsmError> {
+        let file = match operand.strip_prefix('"').and_then(|operand| operand.strip_suffix('"')) {
+            Some(file) => file,
+            None => return Err(location.error("expected a quoted file name to include".to_string())),
+        };
+        let current = self.includes.last().map(|(path, _)| path.clone()).unwrap_or_default();
+        let path = current.parent().unwrap_or_else(|| Path::new("")).join(file);
+        let real_path = canonical(&path);
+        if self.includes.iter().any(|(_, included)| *included == real_path) {
+            return Err(location.error(format!("{} includes itself", path.display())));
+        }
+        let source = fs::read_to_string(&path)
+            .map_err(|err| location.error(format!("could not read {}: {}", path.display(), err)))?;
+        self.includes.push((path.clone(), real_path));
+        self.parse(&source, &path.display().to_string())?;
+        self.includes.pop();
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, symbol: Symbol, location: Location) -> Result<(), AsmError> {
+        if !is_identifier(name) {
+            return Err(location.error(format!("\"{}\" is not a valid name", name)));
+        }
+        if self.symbols.contains_key(name) {
+            return Err(location.error(format!("\"{}\" is already defined", name)));
+        }
+        self.symbols.insert(name.to_string(), symbol);
+        Ok(())
+    }
+
+    // second pass, now that every label is known encode each statement in turn
+    fn encode(&self) -> Result<Vec<u8>, AsmError> {
+        let mut rom = vec![0; self.addr - PROGRAM_START];
+        for statement in self.statements.iter() {
+            let bytes = self.statement(statement)?;
+            let start = statement.addr - PROGRAM_START;
+            rom[start..start + bytes.len()].copy_from_slice(&bytes);
+        }
+        Ok(rom)
+    }
+
+    fn statement(&self, statement: &Statement) -> Result<Vec<u8>, AsmError> {
+        let values = || statement.operands.iter().map(|(text, location)| self.evaluate(text, location));
+        match statement.mnemonic.as_str() {
+            "DB" => return values().map(|value| Ok(check(value?, -0x80, 0xFF, &statement.location)? as u8)).collect(),
+            "DW" => {
+                let mut bytes = Vec::new();
+                for value in values() {
+                    let value = check(value?, -0x8000, 0xFFFF, &statement.location)? as u16;
+                    bytes.extend_from_slice(&value.to_be_bytes());
+                }
+                return Ok(bytes);
+            }
+            _ => {}
+        }
+
+        let operands: Vec<Operand> = statement.operands.iter().map(|(text, location)| operand(text, location)).collect();
+        let addr = |text: &str, location: &Location| Ok(check(self.evaluate(text, location)?, 0, 0xFFF, location)? as u16);
+        let byte = |text: &str, location: &Location| Ok(check(self.evaluate(text, location)?, -0x80, 0xFF, location)? as u8);
+        let nibble = |text: &str, location: &Location| Ok(check(self.evaluate(text, location)?, 0, 0xF, location)? as u8);
+
+        use Operand::*;
+        let instruction = match (statement.mnemonic.as_str(), operands.as_slice()) {
+            ("SCD", [Value(n, at)]) => Instruction::ScrollDown(nibble(n, at)?),
+            ("SCU", [Value(n, at)]) => Instruction::ScrollUp(nibble(n, at)?),
+            ("CLS", []) => Instruction::ClearScreen,
+            ("RET", []) => Instruction::Return,
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::Lores,
+            ("HIGH", []) => Instruction::Hires,
+            ("JP", [Value(nnn, at)]) => Instruction::Jump(addr(nnn, at)?),
+            ("JP", [Register(0), Value(nnn, at)]) => Instruction::JumpWithOffset(addr(nnn, at)?),
+            ("CALL", [Value(nnn, at)]) => Instruction::Call(addr(nnn, at)?),
+            ("SE", [Register(x), Value(kk, at)]) => Instruction::SkipIfEqual(*x, byte(kk, at)?),
+            ("SE", [Register(x), Register(y)]) => Instruction::SkipIfRegistersEqual(*x, *y),
+            ("SNE", [Register(x), Value(kk, at)]) => Instruction::SkipIfNotEqual(*x, byte(kk, at)?),
+            ("SNE", [Register(x), Register(y)]) => Instruction::SkipIfRegistersNotEqual(*x, *y),
+            ("SAVE", [Range(x, y)]) => Instruction::SaveRange(*x, *y),
+            ("LOAD", [Range(x, y)]) => Instruction::LoadRange(*x, *y),
+            ("LD", [Register(x), Value(kk, at)]) => Instruction::Load(*x, byte(kk, at)?),
+            ("LD", [Register(x), Register(y)]) => Instruction::LoadRegister(*x, *y),
+            ("LD", [Keyword("I"), Value(nnn, at)]) => Instruction::LoadI(addr(nnn, at)?),
+            ("LD", [Keyword("I"), Long(..)]) => Instruction::LoadILong,
+            ("LD", [Register(x), Keyword("DT")]) => Instruction::LoadDelayTimer(*x),
+            ("LD", [Register(x), Keyword("K")]) => Instruction::WaitForKey(*x),
+            ("LD", [Keyword("DT"), Register(x)]) => Instruction::SetDelayTimer(*x),
+            ("LD", [Keyword("ST"), Register(x)]) => Instruction::SetSoundTimer(*x),
+            ("LD", [Keyword("F"), Register(x)]) => Instruction::LoadFont(*x),
+            ("LD", [Keyword("HF"), Register(x)]) => Instruction::LoadBigFont(*x),
+            ("LD", [Keyword("B"), Register(x)]) => Instruction::StoreDecimal(*x),
+            ("LD", [Keyword("[I]"), Register(x)]) => Instruction::StoreRegisters(*x),
+            ("LD", [Register(x), Keyword("[I]")]) => Instruction::LoadRegisters(*x),
+            ("LD", [Keyword("R"), Register(x)]) => Instruction::SaveFlags(*x),
+            ("LD", [Register(x), Keyword("R")]) => Instruction::LoadFlags(*x),
+            ("ADD", [Register(x), Value(kk, at)]) => Instruction::Add(*x, byte(kk, at)?),
+            ("ADD", [Register(x), Register(y)]) => Instruction::AddRegisters(*x, *y),
+            ("ADD", [Keyword("I"), Register(x)]) => Instruction::AddToI(*x),
+            ("OR", [Register(x), Register(y)]) => Instruction::Or(*x, *y),
+            ("AND", [Register(x), Register(y)]) => Instruction::And(*x, *y),
+            ("XOR", [Register(x), Register(y)]) => Instruction::Xor(*x, *y),
+            ("SUB", [Register(x), Register(y)]) => Instruction::Subtract(*x, *y),
+            ("SUBN", [Register(x), Register(y)]) => Instruction::SubtractInverted(*x, *y),
+            // the shifts only need the source register on interpreters with the VY quirk
+            ("SHR", [Register(x)]) => Instruction::ShiftRight(*x, *x),
+            ("SHR", [Register(x), Register(y)]) => Instruction::ShiftRight(*x, *y),
+            ("SHL", [Register(x)]) => Instruction::ShiftLeft(*x, *x),
+            ("SHL", [Register(x), Register(y)]) => Instruction::ShiftLeft(*x, *y),
+            ("RND", [Register(x), Value(kk, at)]) => Instruction::Random(*x, byte(kk, at)?),
+            ("DRW", [Register(x), Register(y), Value(n, at)]) => Instruction::Draw(*x, *y, nibble(n, at)?),
+            ("SKP", [Register(x)]) => Instruction::SkipIfKeyPressed(*x),
+            ("SKNP", [Register(x)]) => Instruction::SkipIfKeyNotPressed(*x),
+            ("PLANE", [Value(n, at)]) => Instruction::SelectPlanes(nibble(n, at)?),
+            ("AUDIO", []) => Instruction::LoadAudioPattern,
+            ("PITCH", [Register(x)]) => Instruction::SetPitch(*x),
+            (mnemonic, _) if MNEMONICS.contains(&mnemonic) => {
+                return Err(statement.location.error(format!("invalid operands for {}", mnemonic)));
+            }
+            (mnemonic, _) => return Err(statement.location.error(format!("unknown instruction \"{}\"", mnemonic))),
+        };
+
+        let mut bytes = instruction.encode().to_be_bytes().to_vec();
+        if let [_, Long(nnnn, at)] = operands.as_slice() {
+            let nnnn = check(self.evaluate(nnnn, at)?, 0, 0xFFFF, at)? as u16;
+            bytes.extend_from_slice(&nnnn.to_be_bytes());
+        }
+        Ok(bytes)
+    }
+
+    fn evaluate(&self, text: &str, location: &Location) -> Result<i64, AsmError> {
+        self.evaluate_nested(text, location, 0)
+    }
+
+    // expressions are terms added or subtracted left to right, depth stops constants that are
+    // defined in terms of themselves from recursing forever
+    fn evaluate_nested(&self, text: &str, location: &Location, depth: usize) -> Result<i64, AsmError> {
+        if depth > 64 {
+            return Err(location.error("constant is defined in terms of itself".to_string()));
+        }
+        let mut total: i64 = 0;
+        let mut sign = 1;
+        let mut expect_term = true;
+        let mut pos = 0;
+        while pos < text.len() {
+            let rest = &text[pos..];
+            let c = rest.chars().next().unwrap_or(' ');
+            if c.is_whitespace() {
+                pos += c.len_utf8();
+            } else if expect_term && c == '-' {
+                sign = -sign;
+                pos += 1;
+            } else if !expect_term && (c == '+' || c == '-') {
+                sign = if c == '+' { 1 } else { -1 };
+                expect_term = true;
+                pos += 1;
+            } else if expect_term && (c.is_ascii_alphanumeric() || c == '_') {
+                let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                let term = &rest[..len];
+                let at = location.offset(pos);
+                let value = if c.is_ascii_digit() { parse_number(term, &at)? } else { self.symbol(term, &at, depth)? };
+                total = value.checked_mul(sign).and_then(|value| total.checked_add(value))
+                    .ok_or_else(|| at.error("expression is too large".to_string()))?;
+                sign = 1;
+                expect_term = false;
+                pos += len;
+            } else {
+                return Err(location.offset(pos).error(format!("unexpected \"{}\" in expression", c)));
+            }
+        }
+        if expect_term {
+            return Err(location.offset(pos).error("expected a value".to_string()));
+        }
+        Ok(total)
+    }
+
+    fn symbol(&self, name: &str, location: &Location, depth: usize) -> Result<i64, AsmError> {
+        match self.symbols.get(name) {
+            Some(Symbol::Label(addr)) => Ok(*addr as i64),
+            Some(Symbol::Constant(text, defined_at)) => self.evaluate_nested(text, defined_at, depth + 1),
+            None => Err(location.error(format!("undefined symbol \"{}\"", name))),
+        }
+    }
+}
+
+impl Statement {
+    // the size is known without evaluating any operands so labels can be laid out in the first pass
+    fn size(&self) -> usize {
+        match self.mnemonic.as_str() {
+            "DB" => self.operands.len(),
+            "DW" => 2 * self.operands.len(),
+            "LD" if self.operands.get(1).is_some_and(|(text, location)| matches!(operand(text, location), Operand::Long(..))) => 4,
+            _ => 2,
+        }
+    }
+}
+
+fn operand<'a>(text: &'a str, location: &'a Location) -> Operand<'a> {
+    if let Some(register) = register(text) {
+        return Operand::Register(register);
+    }
+    if let Some((x, y)) = text.split_once('-') {
+        if let (Some(x), Some(y)) = (register(x.trim()), register(y.trim())) {
+            return Operand::Range(x, y);
+        }
+    }
+    let (word, rest) = split_word(text);
+    if word.eq_ignore_ascii_case("long") && !rest.is_empty() {
+        let column = rest.as_ptr() as usize - text.as_ptr() as usize;
+        return Operand::Long(rest, location.offset(column));
+    }
+    match ["I", "[I]", "DT", "ST", "K", "F", "HF", "B", "R"].iter().find(|keyword| keyword.eq_ignore_ascii_case(text)) {
+        Some(keyword) => Operand::Keyword(keyword),
+        None => Operand::Value(text, location),
+    }
+}
+
+fn register(text: &str) -> Option<u8> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => digit.to_digit(16).map(|digit| digit as u8),
+        _ => None,
+    }
+}
+
+fn parse_number(text: &str, location: &Location) -> Result<i64, AsmError> {
+    let lower = text.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+    } else {
+        lower.parse()
+    };
+    parsed.map_err(|error| match error.kind() {
+        IntErrorKind::PosOverflow => location.error(format!("number \"{}\" is too large", text)),
+        _ => location.error(format!("invalid number \"{}\"", text)),
+    })
+}
+
+fn check(value: i64, min: i64, max: i64, location: &Location) -> Result<i64, AsmError> {
+    if value < min || value > max {
+        return Err(location.error(format!("value {} is out of range, expected {} to {}", value, min, max)));
+    }
+    Ok(value)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_well = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    starts_well && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && register(name).is_none()
+        && !KEYWORDS.iter().any(|keyword| keyword.eq_ignore_ascii_case(name))
+}
+
+// everything before a ; that isn't inside a quoted file name
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// the first whitespace separated word and whatever follows it, both trimmed
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.find(char::is_whitespace) {
+        Some(end) => (&text[..end], text[end..].trim()),
+        None => (text, ""),
+    }
+}
+
+// the real path of a file, so the same file reached through different paths is recognised. names
+// that aren't files, like that of source that didn't come from one, are used as they are
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a fresh directory for the files of a test
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("chip8-assembler-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("sub")).unwrap();
+        directory
+    }
+
+    fn assemble_file(path: &Path) -> Result<Vec<u8>, AsmError> {
+        assemble(&fs::read_to_string(path).unwrap(), &path.display().to_string())
+    }
+
+    #[test]
+    fn unicode_whitespace_in_expressions() {
+        assert_eq!(assemble("LD V0, 1\u{a0}+ 2", "test.asm"), Ok(vec![0x60, 0x03]));
+    }
+
+    #[test]
+    fn expressions_that_overflow() {
+        let error = assemble("LD V0, 0x7FFFFFFFFFFFFFFF + 1", "test.asm").unwrap_err();
+        assert_eq!((error.line, error.column, error.message.as_str()), (1, 29, "expression is too large"));
+        let error = assemble("LD V0, 99999999999999999999", "test.asm").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 8));
+        assert!(error.message.ends_with("is too large"), "{}", error);
+    }
+
+    #[test]
+    fn nested_includes() {
+        let directory = directory("nested");
+        fs::write(directory.join("main.asm"), "LD V0, 1\ninclude \"sub/a.asm\"\n").unwrap();
+        fs::write(directory.join("sub/a.asm"), "LD V1, 2\ninclude \"b.asm\"\n").unwrap();
+        fs::write(directory.join("sub/b.asm"), "LD V2, 3\n").unwrap();
+        assert_eq!(assemble_file(&directory.join("main.asm")), Ok(vec![0x60, 0x01, 0x61, 0x02, 0x62, 0x03]));
+    }
+
+    #[test]
+    fn self_include() {
+        let directory = directory("self");
+        fs::write(directory.join("a.asm"), "include \"sub/../a.asm\"\n").unwrap();
+        let error = assemble_file(&directory.join("a.asm")).unwrap_err();
+        assert!(error.message.ends_with("includes itself"), "{}", error);
+    }
+
+    #[test]
+    fn include_cycle() {
+        let directory = directory("cycle");
+        fs::write(directory.join("a.asm"), "include \"sub/b.asm\"\n").unwrap();
+        fs::write(directory.join("sub/b.asm"), "include \"../a.asm\"\n").unwrap();
+        let error = assemble_file(&directory.join("a.asm")).unwrap_err();
+        assert!(error.message.ends_with("includes itself"), "{}", error);
+    }
+}
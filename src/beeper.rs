@@ -0,0 +1,60 @@
+// the default beep used until a program loads an XO-CHIP audio pattern
+const SQUARE_WAVE_FREQUENCY: f32 = 240.0;
+
+// generates the sound of the CHIP8 beeper, either the default square wave or a loop over the 128
+// bits of an XO-CHIP pattern playing a high sample for every set bit
+pub struct Beeper {
+    sample_rate: f32,
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
+    pattern: Option<[u8; 16]>,
+    pitch: u8,
+}
+
+impl Beeper {
+    pub fn new(sample_rate: u32) -> Beeper {
+        Beeper {
+            sample_rate: sample_rate as f32,
+            phase_inc: SQUARE_WAVE_FREQUENCY / sample_rate as f32,
+            phase: 0.0,
+            volume: 0.25,
+            pattern: None,
+            pitch: 64,
+        }
+    }
+
+    // the pattern is played back at 4000*2^((pitch-64)/48) bits per second, changing it starts
+    // playing it from the beginning
+    pub fn set_pattern(&mut self, pattern: Option<[u8; 16]>, pitch: u8) {
+        if pattern == self.pattern && pitch == self.pitch {
+            return;
+        }
+        self.pattern = pattern;
+        self.pitch = pitch;
+        self.phase = 0.0;
+        self.phase_inc = match pattern {
+            Some(_) => 4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0) / self.sample_rate,
+            None => SQUARE_WAVE_FREQUENCY / self.sample_rate,
+        };
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            match self.pattern {
+                // phase counts bits through the pattern
+                Some(pattern) => {
+                    let bit = self.phase as usize;
+                    let high = (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1;
+                    *x = self.volume * if high { 1.0 } else { -1.0 };
+                    self.phase = (self.phase + self.phase_inc) % 128.0;
+                }
+                // Generate a square wave
+                None => {
+                    *x = self.volume * if self.phase < 0.5 { 1.0 } else { -1.0 };
+                    self.phase = (self.phase + self.phase_inc) % 1.0;
+                }
+            }
+        }
+    }
+}
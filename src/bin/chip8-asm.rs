@@ -0,0 +1,52 @@
+use std::{env, fs};
+use chip8_core::assembler::assemble;
+use std::path::Path;
+use std::process::exit;
+
+// assemble a source file into a ROM, usage: chip8-asm <source> [-o <rom>]. the ROM is written next
+// to the source with a .ch8 extension unless an output is given
+fn main() {
+    let mut source_file = None;
+    let mut output = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "-o" {
+            output = match args.next() {
+                Some(output) => Some(output),
+                None => {
+                    eprintln!("Error, -o expects the file to write the ROM to");
+                    exit(1);
+                }
+            };
+        } else {
+            source_file = Some(arg);
+        }
+    }
+    let source_file = match source_file {
+        Some(source_file) => source_file,
+        None => {
+            eprintln!("Error, please provide a file to assemble as an argument");
+            exit(1);
+        }
+    };
+    let output = output.unwrap_or_else(|| Path::new(&source_file).with_extension("ch8").display().to_string());
+
+    let source = match fs::read_to_string(&source_file) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Error, could not read {}: {}", source_file, err);
+            exit(1);
+        }
+    };
+    let rom = match assemble(&source, &source_file) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("Error, {}", err);
+            exit(1);
+        }
+    };
+    if let Err(err) = fs::write(&output, &rom) {
+        eprintln!("Error, could not write {}: {}", output, err);
+        exit(1);
+    }
+}
@@ -0,0 +1,40 @@
+use std::{env, fs};
+use chip8_core::cpu::PROGRAM_START;
+use chip8_core::disassembler::{Disassembly, Syntax};
+use std::process::exit;
+
+// print an annotated listing of a ROM, usage: chip8-disasm [--syntax cowgod|octo] <rom>
+fn main() {
+    let mut syntax = Syntax::Cowgod;
+    let mut rom_file = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--syntax" {
+            syntax = match args.next().unwrap_or_default().parse() {
+                Ok(syntax) => syntax,
+                Err(message) => {
+                    eprintln!("Error, {}", message);
+                    exit(1);
+                }
+            };
+        } else {
+            rom_file = Some(arg);
+        }
+    }
+    let rom_file = match rom_file {
+        Some(rom_file) => rom_file,
+        None => {
+            eprintln!("Error, please provide a ROM to disassemble as an argument");
+            exit(1);
+        }
+    };
+
+    let rom = match fs::read(&rom_file) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("Error, could not read {}: {}", rom_file, err);
+            exit(1);
+        }
+    };
+    print!("{}", Disassembly::new(&rom, PROGRAM_START).listing(syntax));
+}
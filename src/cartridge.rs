@@ -0,0 +1,114 @@
+use std::fmt;
+use serde_json::Value;
+use crate::assembler::AsmError;
+use crate::display::{parse_colour, Palette};
+use crate::octo;
+use crate::quirks::{IndexIncrement, Quirks};
+
+// Octo shares programs as "cartridge" GIFs, a picture of a cartridge whose pixels also carry the
+// program's source and the options it should be run with. the low 2 bits of every pixel's palette
+// index are payload, read 4 pixels to a byte most significant bits first. the payload is a 4 byte
+// big endian length followed by that many bytes of JSON: {"options": {...}, "program": "<source>"}
+pub const GIF_MAGIC: &[u8] = b"GIF8";
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Gif(String),
+    Truncated,
+    Json(String),
+    Compile(AsmError),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::Gif(message) => write!(f, "Could not decode cartridge image: {}", message),
+            CartridgeError::Truncated => write!(f, "Cartridge payload is truncated"),
+            CartridgeError::Json(message) => write!(f, "Cartridge payload is invalid: {}", message),
+            CartridgeError::Compile(err) => write!(f, "Could not compile cartridge program: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+// the runtime options a cartridge recommends, anything it doesn't specify is left as None
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CartridgeOptions {
+    pub instructions_per_frame: Option<usize>,
+    pub quirks: Option<Quirks>,
+    pub palette: Option<Palette>,
+}
+
+pub struct Cartridge {
+    pub rom: Vec<u8>,
+    pub options: CartridgeOptions,
+}
+
+pub fn is_cartridge(data: &[u8]) -> bool {
+    data.starts_with(GIF_MAGIC)
+}
+
+// decode the payload of a cartridge and compile its program, the name is used in compile errors
+pub fn load(data: &[u8], name: &str) -> Result<Cartridge, CartridgeError> {
+    let payload = payload(data)?;
+    let json: Value = serde_json::from_slice(&payload).map_err(|err| CartridgeError::Json(err.to_string()))?;
+    let program = match json.get("program").and_then(Value::as_str) {
+        Some(program) => program,
+        None => return Err(CartridgeError::Json("missing program".to_string())),
+    };
+    let rom = octo::compile(program, name).map_err(CartridgeError::Compile)?;
+    let options = json.get("options").map(options).unwrap_or_default();
+    Ok(Cartridge { rom, options })
+}
+
+fn payload(data: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    let mut decode_options = gif::DecodeOptions::new();
+    decode_options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = decode_options.read_info(data).map_err(|err| CartridgeError::Gif(err.to_string()))?;
+
+    // the payload carries on from one frame into the next
+    let mut pixels = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(|err| CartridgeError::Gif(err.to_string()))? {
+        pixels.extend_from_slice(&frame.buffer);
+    }
+    let bytes: Vec<u8> = pixels.chunks_exact(4)
+        .map(|pixels| pixels.iter().fold(0, |byte, pixel| byte << 2 | (pixel & 3)))
+        .collect();
+
+    if bytes.len() < 4 {
+        return Err(CartridgeError::Truncated);
+    }
+    let len = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    match bytes.get(4..4 + len) {
+        Some(payload) => Ok(payload.to_vec()),
+        None => Err(CartridgeError::Truncated),
+    }
+}
+
+// Octo names its quirks after the behaviour that differs from the original interpreter, so most of
+// them are the opposite of ours
+fn options(options: &Value) -> CartridgeOptions {
+    let flag = |name: &str| options.get(name).and_then(Value::as_bool).unwrap_or(false);
+    let load_store_increment = if flag("loadStoreQuirks") { IndexIncrement::Unchanged } else { IndexIncrement::ByXPlusOne };
+    let quirks = Quirks {
+        shift_uses_vy: !flag("shiftQuirks"),
+        load_store_increment,
+        jump_uses_vx: flag("jumpQuirks"),
+        vf_reset: flag("logicQuirks"),
+        clip_sprites: flag("clipQuirks"),
+        display_wait: flag("vBlankQuirks"),
+    };
+
+    let colour = |name: &str| options.get(name).and_then(Value::as_str).and_then(parse_colour);
+    let palette = match (colour("backgroundColor"), colour("fillColor"), colour("fillColor2"), colour("blendColor")) {
+        (Some(background), Some(fill), Some(fill2), Some(blend)) => Some([background, fill, fill2, blend]),
+        _ => None,
+    };
+
+    CartridgeOptions {
+        instructions_per_frame: options.get("tickrate").and_then(Value::as_u64).map(|rate| rate as usize),
+        quirks: Some(quirks),
+        palette,
+    }
+}
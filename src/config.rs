@@ -0,0 +1,48 @@
+use crate::theme::{parse_palette, Theme};
+
+// the settings file read at startup, every line is a "key = value" setting or a # comment:
+//
+//     # the theme to start in, one of the built in themes or one defined below
+//     theme = amber
+//     # a theme of your own, the off and on colours or all four XO-CHIP plane colours
+//     theme.mine = #000000 #ff00ff
+//     # the phosphor filter to start with, off, fade[:amount] or blend[:frames]
+//     phosphor = fade:0.7
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub theme: Option<String>,
+    pub phosphor: Option<String>,
+    // user defined themes, in the order they were defined
+    pub themes: Vec<Theme>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (number, line) in text.lines().enumerate() {
+            // colours are written with a # too, so only whole lines can be comments
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(format!("line {}: expected a key = value setting", number + 1)),
+            };
+
+            if key == "theme" {
+                config.theme = Some(value.to_string());
+            } else if key == "phosphor" {
+                config.phosphor = Some(value.to_string());
+            } else if let Some(name) = key.strip_prefix("theme.") {
+                let palette = parse_palette(value)
+                    .ok_or_else(|| format!("line {}: expected 2 or 4 hex colours for theme {}", number + 1, name))?;
+                config.themes.retain(|theme| theme.name != name);
+                config.themes.push(Theme::new(name, palette));
+            } else {
+                return Err(format!("line {}: unknown setting \"{}\"", number + 1, key));
+            }
+        }
+        Ok(config)
+    }
+}
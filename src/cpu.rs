@@ -1,7 +1,14 @@
-use crate::drivers::screen_driver::{CHIP8_WIDTH, CHIP8_HEIGHT};
+use crate::display::{Display, ALL_PLANES, PLANES};
+use crate::instruction::Instruction;
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::save_state::{StateError, StateReader, StateWriter};
+use crate::watchpoint::{WatchHit, Watchpoint};
 use rand::Rng;
+use std::fmt;
 
-const CHIP8_RAM: usize = 4096;
+// the XO-CHIP address space, programs for the original CHIP8 only ever touch the first 4 KiB
+pub const CHIP8_RAM: usize = 0x10000;
+pub const PROGRAM_START: usize = 0x200;
 const OPCODE_SIZE: usize = 2;
 
 // built in sprites in memory for the CHIP8 chip
@@ -88,13 +95,61 @@ pub const FONT: [u8; 80] = [
     0x80,
 ];
 
+// the SUPER-CHIP 8x10 high resolution font, stored in RAM directly after the small font
+pub const BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+const BIG_FONT_ADDR: usize = 0x50;
+
 // return the CPU state after each tick to tell the main loop to play a sound or update the display
 pub struct CpuState<'a>{
-    pub display: &'a[[u8; CHIP8_WIDTH]; CHIP8_HEIGHT],
+    pub display: &'a Display,
     pub display_updated: bool,
-    pub play_sound: bool
+    pub play_sound: bool,
+    pub exited: bool,
+    // the XO-CHIP audio pattern, None until a program loads one, and its playback pitch
+    pub audio_pattern: Option<[u8; 16]>,
+    pub pitch: u8
 }
 
+// errors raised by programs doing something the CPU can't carry out. the instruction that caused
+// the error is not executed and the PC is left pointing at it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CpuError {
+    StackOverflow,
+    StackUnderflow,
+    MemoryOutOfBounds { addr: usize },
+    InvalidOpcode { pc: usize, opcode: u16 },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::StackOverflow => write!(f, "Stack overflow"),
+            CpuError::StackUnderflow => write!(f, "Stack underflow"),
+            CpuError::MemoryOutOfBounds { addr } => write!(f, "Memory access out of bounds at {:#x}", addr),
+            CpuError::InvalidOpcode { pc, opcode } => write!(f, "Invalid opcode {:04x} at {:#x}", opcode, pc),
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
 // PC counter next state
 enum PCState {
     Next,
@@ -113,8 +168,9 @@ impl PCState {
     }
 }
 
+#[derive(Clone)]
 pub struct CPU {
-    display: [[u8; CHIP8_WIDTH]; CHIP8_HEIGHT],
+    display: Display,
     display_update: bool,
     ram: [u8; CHIP8_RAM],
     stack: [usize; 16],
@@ -127,18 +183,27 @@ pub struct CPU {
     keypad: [bool; 16],
     keypad_waiting: bool,
     keypad_register: usize,
+    quirks: Quirks,
+    vblank_ready: bool,
+    rpl_flags: [u8; 16],
+    exited: bool,
+    planes: u8,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    watchpoints: Vec<Watchpoint>,
+    watch_hits: Vec<WatchHit>,
 }
 
 impl CPU{
-    // initialize the RAM and copy the font values into the RAM
-    pub fn new() -> CPU{
+    // initialize the RAM and copy the font values into the RAM, the quirks select how the
+    // ambiguous instructions behave
+    pub fn new(quirks: Quirks) -> CPU{
         let mut ram = [0u8; CHIP8_RAM];
-        for i in 0..FONT.len() {
-            ram[i] = FONT[i];
-        }
+        ram[..FONT.len()].copy_from_slice(&FONT);
+        ram[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
 
         CPU {
-            display: [[0; CHIP8_WIDTH]; CHIP8_HEIGHT],
+            display: Display::new(),
             display_update: false,
             ram,
             stack: [0; 16],
@@ -151,295 +216,649 @@ impl CPU{
             keypad: [false; 16],
             keypad_waiting: false,
             keypad_register: 0,
+            quirks,
+            vblank_ready: true,
+            rpl_flags: [0; 16],
+            exited: false,
+            planes: 1,
+            audio_pattern: None,
+            pitch: 64,
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+        }
+    }
+
+    // signal the start of a new frame, this happens 60 times a second and is what drives the timers
+    // as well as releasing a draw held by the display wait quirk
+    pub fn vblank(&mut self){
+        self.vblank_ready = true;
+        if self.delay_timer > 0{
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0{
+            self.sound_timer -= 1;
+        }
+    }
+
+    // serialize everything the running program can observe, the quirks are configuration and are
+    // not part of the state. the ROM hash is stored so the state can only be restored into the same ROM
+    pub fn save_state(&self, rom_hash: u64) -> Vec<u8>{
+        let mut writer = StateWriter::new(rom_hash);
+        writer.bytes(&self.ram);
+        self.display.save_state(&mut writer);
+        for &addr in self.stack.iter() {
+            writer.u32(addr as u32);
         }
+        writer.u8(self.sp as u8);
+        writer.u32(self.pc as u32);
+        writer.u32(self.i as u32);
+        writer.bytes(&self.v_registers);
+        writer.u8(self.delay_timer);
+        writer.u8(self.sound_timer);
+        writer.bool(self.keypad_waiting);
+        writer.u8(self.keypad_register as u8);
+        writer.bool(self.vblank_ready);
+        writer.bytes(&self.rpl_flags);
+        writer.bool(self.exited);
+        writer.u8(self.planes);
+        writer.bool(self.audio_pattern.is_some());
+        writer.bytes(&self.audio_pattern.unwrap_or_default());
+        writer.u8(self.pitch);
+        writer.finish()
+    }
+
+    // restore a state written by save_state, on error the CPU is left untouched
+    pub fn load_state(&mut self, data: &[u8], rom_hash: u64) -> Result<(), StateError>{
+        let mut reader = StateReader::new(data, rom_hash)?;
+        let mut cpu = self.clone();
+        reader.fill(&mut cpu.ram)?;
+        cpu.display.load_state(&mut reader)?;
+        for addr in cpu.stack.iter_mut() {
+            *addr = reader.u32()? as usize;
+        }
+        cpu.sp = reader.u8()? as usize;
+        cpu.pc = reader.u32()? as usize;
+        cpu.i = reader.u32()? as usize;
+        reader.fill(&mut cpu.v_registers)?;
+        cpu.delay_timer = reader.u8()?;
+        cpu.sound_timer = reader.u8()?;
+        cpu.keypad_waiting = reader.bool()?;
+        cpu.keypad_register = reader.u8()? as usize;
+        cpu.vblank_ready = reader.bool()?;
+        reader.fill(&mut cpu.rpl_flags)?;
+        cpu.exited = reader.bool()?;
+        cpu.planes = reader.u8()?;
+        let has_pattern = reader.bool()?;
+        let mut pattern = [0; 16];
+        reader.fill(&mut pattern)?;
+        cpu.audio_pattern = if has_pattern { Some(pattern) } else { None };
+        cpu.pitch = reader.u8()?;
+        reader.finish()?;
+        if cpu.sp > cpu.stack.len() || cpu.keypad_register > 0xF || cpu.planes & !ALL_PLANES != 0 {
+            return Err(StateError::InvalidValue);
+        }
+        *self = cpu;
+        Ok(())
+    }
+
+    // read access to the CPU for debuggers and other tools
+    pub fn display(&self) -> &Display{
+        &self.display
+    }
+
+    pub fn ram(&self) -> &[u8]{
+        &self.ram
+    }
+
+    pub fn v_registers(&self) -> &[u8; 16]{
+        &self.v_registers
+    }
+
+    pub fn i(&self) -> usize{
+        self.i
+    }
+
+    pub fn pc(&self) -> usize{
+        self.pc
+    }
+
+    pub fn sp(&self) -> usize{
+        self.sp
+    }
+
+    // the return addresses currently on the stack, oldest first
+    pub fn stack(&self) -> &[usize]{
+        &self.stack[..self.sp]
+    }
+
+    pub fn delay_timer(&self) -> u8{
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8{
+        self.sound_timer
+    }
+
+    pub fn keypad_waiting(&self) -> bool{
+        self.keypad_waiting
+    }
+
+    // whether the PC is on a jump to itself, which is how most programs stop once they are done
+    pub fn is_spinning(&self) -> bool{
+        self.opcode_at(self.pc).ok().and_then(Instruction::decode) == Some(Instruction::Jump(self.pc as u16))
+    }
+
+    pub fn quirks(&self) -> Quirks{
+        self.quirks
+    }
+
+    // replace the memory ranges being watched for reads and writes
+    pub fn set_watchpoints(&mut self, watchpoints: Vec<Watchpoint>){
+        self.watchpoints = watchpoints;
+    }
+
+    // hand back the watched accesses made since the last call
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit>{
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    // move past the instruction at the PC without running it, used to carry on after an error
+    pub fn skip_instruction(&mut self){
+        self.pc += OPCODE_SIZE;
     }
 
     // load the ROM as a byte array into RAM
     pub fn load(&mut self, dat: &[u8]){
-        let mut addr = 0x200;
-        for (_i, &byte) in dat.iter().enumerate() {
-            if addr < 4096 {
+        for (addr, &byte) in (PROGRAM_START..).zip(dat) {
+            if addr < CHIP8_RAM {
                 self.ram[addr] = byte;
             } else {
                 break;
             }
-            addr+=1;
         }
     }
 
-    // clear the screen
-    fn clear_screen(&mut self) -> PCState {
-        for y in 0..CHIP8_HEIGHT {
-            for x in 0..CHIP8_WIDTH {
-                self.display[y][x] = 0;
+    // bounds checked access to a range of memory, instruction fetches go through here directly and
+    // are not reported to watchpoints
+    fn memory(&self, addr: usize, len: usize) -> Result<&[u8], CpuError> {
+        self.ram.get(addr..addr + len).ok_or(CpuError::MemoryOutOfBounds { addr: addr.max(CHIP8_RAM) })
+    }
+
+    // every memory access made by an instruction goes through read_memory or write_memory so
+    // watchpoints can see it
+    fn read_memory(&mut self, addr: usize, len: usize) -> Result<&[u8], CpuError> {
+        self.memory(addr, len)?;
+        self.track_access(addr, len, false);
+        Ok(&self.ram[addr..addr + len])
+    }
+
+    fn write_memory(&mut self, addr: usize, data: &[u8]) -> Result<(), CpuError> {
+        self.memory(addr, data.len())?;
+        self.ram[addr..addr + data.len()].copy_from_slice(data);
+        self.track_access(addr, data.len(), true);
+        Ok(())
+    }
+
+    // record any watched addresses in the access, this is a single branch when nothing is watched
+    fn track_access(&mut self, addr: usize, len: usize, write: bool) {
+        if self.watchpoints.is_empty() {
+            return;
+        }
+        for addr in addr..addr + len {
+            if self.watchpoints.iter().any(|watchpoint| watchpoint.hit(addr, write)) {
+                self.watch_hits.push(WatchHit { pc: self.pc, addr, write, value: self.ram[addr] });
             }
         }
+    }
+
+    // clear the screen
+    // clearing and scrolling only affect the currently selected XO-CHIP planes
+    fn clear_screen(&mut self) -> Result<PCState, CpuError> {
+        self.display.clear(self.planes);
+        self.display_update = true;
+        Ok(PCState::Next)
+    }
+
+    // SUPER-CHIP scrolling and resolution switching
+    fn scroll_down(&mut self, n: usize) -> Result<PCState, CpuError> {
+        self.display.scroll_down(n, self.planes);
+        self.display_update = true;
+        Ok(PCState::Next)
+    }
+
+    fn scroll_up(&mut self, n: usize) -> Result<PCState, CpuError> {
+        self.display.scroll_up(n, self.planes);
+        self.display_update = true;
+        Ok(PCState::Next)
+    }
+
+    fn scroll_right(&mut self) -> Result<PCState, CpuError> {
+        self.display.scroll_right(4, self.planes);
         self.display_update = true;
-        PCState::Next
+        Ok(PCState::Next)
+    }
+
+    fn scroll_left(&mut self) -> Result<PCState, CpuError> {
+        self.display.scroll_left(4, self.planes);
+        self.display_update = true;
+        Ok(PCState::Next)
+    }
+
+    fn set_hires(&mut self, hires: bool) -> Result<PCState, CpuError> {
+        self.display.set_hires(hires);
+        self.display_update = true;
+        Ok(PCState::Next)
+    }
+
+    // stop the interpreter, the PC stays on the exit instruction
+    fn exit(&mut self) -> Result<PCState, CpuError> {
+        self.exited = true;
+        Ok(PCState::Jump(self.pc))
     }
 
     // The following functions are implementations of the equivalent opcodes needed for later
-    fn ret(&mut self) -> PCState {
+    fn ret(&mut self) -> Result<PCState, CpuError> {
+        if self.sp == 0 {
+            return Err(CpuError::StackUnderflow);
+        }
         self.sp -= 1;
-        PCState::Jump(self.stack[self.sp])
+        Ok(PCState::Jump(self.stack[self.sp]))
     }
 
-    fn jump(&mut self, nnn: usize) -> PCState {
-        PCState::Jump(nnn)
+    fn jump(&mut self, nnn: usize) -> Result<PCState, CpuError> {
+        Ok(PCState::Jump(nnn))
     }
 
-    fn call(&mut self, nnn: usize) -> PCState {
+    fn call(&mut self, nnn: usize) -> Result<PCState, CpuError> {
+        if self.sp == self.stack.len() {
+            return Err(CpuError::StackOverflow);
+        }
         self.stack[self.sp] = self.pc + OPCODE_SIZE;
         self.sp += 1;
-        PCState::Jump(nnn)
+        Ok(PCState::Jump(nnn))
+    }
+
+    fn skip_if_equal_register(&mut self, x: usize, kk: u8) -> Result<PCState, CpuError> {
+        Ok(PCState::skip_if(self.v_registers[x] == kk))
+    }
+
+    fn skip_if_not_equal_register(&mut self, x: usize, kk: u8) -> Result<PCState, CpuError> {
+        Ok(PCState::skip_if(self.v_registers[x] != kk))
+    }
+
+    fn skip_if_registers_equal(&mut self, x: usize, y: usize) -> Result<PCState, CpuError> {
+        Ok(PCState::skip_if(self.v_registers[x] == self.v_registers[y]))
     }
 
-    fn skip_if_equal_register(&mut self, x: usize, kk: u8) -> PCState {
-        PCState::skip_if(self.v_registers[x] == kk)
+    fn skip_if_registers_not_equal(&mut self, x: usize, y: usize) -> Result<PCState, CpuError> {
+        Ok(PCState::skip_if(self.v_registers[x] != self.v_registers[y]))
     }
 
-    fn skip_if_not_equal_register(&mut self, x: usize, kk: u8) -> PCState {
-        PCState::skip_if(self.v_registers[x] != kk)
+    // XO-CHIP register range save and load, the range can run in either direction and I is left untouched
+    fn save_register_range(&mut self, x: usize, y: usize) -> Result<PCState, CpuError> {
+        let mut data = [0u8; 16];
+        for (offset, register) in Self::register_range(x, y).enumerate() {
+            data[offset] = self.v_registers[register];
+        }
+        self.write_memory(self.i, &data[..x.max(y) - x.min(y) + 1])?;
+        Ok(PCState::Next)
     }
 
-    fn skip_if_registers_equal(&mut self, x: usize, y: usize) -> PCState {
-        PCState::skip_if(self.v_registers[x] == self.v_registers[y])
+    fn load_register_range(&mut self, x: usize, y: usize) -> Result<PCState, CpuError> {
+        let mut data = [0u8; 16];
+        let len = x.max(y) - x.min(y) + 1;
+        data[..len].copy_from_slice(self.read_memory(self.i, len)?);
+        for (offset, register) in Self::register_range(x, y).enumerate() {
+            self.v_registers[register] = data[offset];
+        }
+        Ok(PCState::Next)
     }
 
-    fn skip_if_registers_not_equal(&mut self, x: usize, y: usize) -> PCState {
-        PCState::skip_if(self.v_registers[x] != self.v_registers[y])
+    fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+        if x <= y {
+            Box::new(x..=y)
+        } else {
+            Box::new((y..=x).rev())
+        }
     }
 
-    fn skip_if_key_pressed(&mut self, x: usize) -> PCState {
-        PCState::skip_if(self.keypad[self.v_registers[x] as usize])
+    fn skip_if_key_pressed(&mut self, x: usize) -> Result<PCState, CpuError> {
+        Ok(PCState::skip_if(self.keypad[self.v_registers[x] as usize & 0xF]))
     }
 
-    fn skip_if_key_not_pressed(&mut self, x: usize) -> PCState {
-        PCState::skip_if(!self.keypad[self.v_registers[x] as usize])
+    fn skip_if_key_not_pressed(&mut self, x: usize) -> Result<PCState, CpuError> {
+        Ok(PCState::skip_if(!self.keypad[self.v_registers[x] as usize & 0xF]))
     }
 
-    fn load_register(&mut self, x: usize, kk: u8) -> PCState {
+    fn load_register(&mut self, x: usize, kk: u8) -> Result<PCState, CpuError> {
         self.v_registers[x] = kk;
-        PCState::Next
+        Ok(PCState::Next)
     }
 
-    fn add_register(&mut self, x: usize, kk: u8) -> PCState {
+    fn add_register(&mut self, x: usize, kk: u8) -> Result<PCState, CpuError> {
         let vx = self.v_registers[x] as u16;
         let val = kk as u16;
         let result = vx + val;
         self.v_registers[x] = result as u8;
-        PCState::Next
+        Ok(PCState::Next)
     }
 
-    fn load_register_into_register(&mut self, x: usize, y: usize) -> PCState {
+    fn load_register_into_register(&mut self, x: usize, y: usize) -> Result<PCState, CpuError> {
         self.v_registers[x] = self.v_registers[y];
-        PCState::Next
+        Ok(PCState::Next)
     }
 
-    fn or_register_by_register(&mut self, x: usize, y: usize) -> PCState {
+    fn or_register_by_register(&mut self, x: usize, y: usize) -> Result<PCState, CpuError> {
         self.v_registers[x] |= self.v_registers[y];
-        PCState::Next
+        if self.quirks.vf_reset {
+            self.v_registers[0xF] = 0;
+        }
+        Ok(PCState::Next)
     }
 
-    fn and_register_by_register(&mut self, x: usize, y: usize) -> PCState {
+    fn and_register_by_register(&mut self, x: usize, y: usize) -> Result<PCState, CpuError> {
         self.v_registers[x] &= self.v_registers[y];
-        PCState::Next
+        if self.quirks.vf_reset {
+            self.v_registers[0xF] = 0;
+        }
+        Ok(PCState::Next)
     }
 
-    fn xor_register_by_register(&mut self, x: usize, y:usize) -> PCState {
+    fn xor_register_by_register(&mut self, x: usize, y:usize) -> Result<PCState, CpuError> {
         self.v_registers[x] ^= self.v_registers[y];
-        PCState::Next
+        if self.quirks.vf_reset {
+            self.v_registers[0xF] = 0;
+        }
+        Ok(PCState::Next)
     }
 
-    fn add_registers_and_carry(&mut self, x: usize, y:usize) -> PCState {
+    fn add_registers_and_carry(&mut self, x: usize, y:usize) -> Result<PCState, CpuError> {
         let vx = self.v_registers[x] as u16;
         let vy = self.v_registers[y] as u16;
         let result = vx + vy;
         self.v_registers[x] = result as u8;
         self.v_registers[0xF] = if result > 0xFF { 1 } else { 0 };
-        PCState::Next
+        Ok(PCState::Next)
     }
 
-    fn subtract_and_carry(&mut self, x: usize, y: usize) -> PCState {
-        self.v_registers[0xF] = if self.v_registers[x] > self.v_registers[y] { 1 } else { 0 };
-        self.v_registers[x] = self.v_registers[x].wrapping_sub(self.v_registers[y]);
-        PCState::Next
+    // VF is 1 when there was no borrow, and is written last so it wins when it is also Vx
+    fn subtract_and_carry(&mut self, x: usize, y: usize) -> Result<PCState, CpuError> {
+        let (vx, vy) = (self.v_registers[x], self.v_registers[y]);
+        self.v_registers[x] = vx.wrapping_sub(vy);
+        self.v_registers[0xF] = if vx >= vy { 1 } else { 0 };
+        Ok(PCState::Next)
     }
 
-    fn shift_right(&mut self, x: usize) -> PCState {
-        self.v_registers[0xF] = self.v_registers[x] & 1;
-        self.v_registers[x] >>= 1;
-        PCState::Next
+    fn shift_right(&mut self, x: usize, y: usize) -> Result<PCState, CpuError> {
+        let value = if self.quirks.shift_uses_vy { self.v_registers[y] } else { self.v_registers[x] };
+        self.v_registers[x] = value >> 1;
+        self.v_registers[0xF] = value & 1;
+        Ok(PCState::Next)
     }
 
-    fn shift_left(&mut self, x: usize) -> PCState {
-        self.v_registers[0xF] = (self.v_registers[x] & 0b10000000) >> 7;
-        self.v_registers[x] <<= 1;
-        PCState::Next
+    fn shift_left(&mut self, x: usize, y: usize) -> Result<PCState, CpuError> {
+        let value = if self.quirks.shift_uses_vy { self.v_registers[y] } else { self.v_registers[x] };
+        self.v_registers[x] = value << 1;
+        self.v_registers[0xF] = (value & 0b10000000) >> 7;
+        Ok(PCState::Next)
     }
 
-    fn subtract_and_carry_inverted(&mut self, x: usize, y: usize) -> PCState {
-        self.v_registers[0x0f] = if self.v_registers[y] > self.v_registers[x] { 1 } else { 0 };
-        self.v_registers[x] = self.v_registers[y].wrapping_sub(self.v_registers[x]);
-        PCState::Next
+    fn subtract_and_carry_inverted(&mut self, x: usize, y: usize) -> Result<PCState, CpuError> {
+        let (vx, vy) = (self.v_registers[x], self.v_registers[y]);
+        self.v_registers[x] = vy.wrapping_sub(vx);
+        self.v_registers[0xF] = if vy >= vx { 1 } else { 0 };
+        Ok(PCState::Next)
     }
 
-    fn load_i(&mut self, nnn: usize) -> PCState {
+    fn load_i(&mut self, nnn: usize) -> Result<PCState, CpuError> {
         self.i = nnn;
-        PCState::Next
+        Ok(PCState::Next)
+    }
+
+    // F000 NNNN loads a full 16 bit address from the word following the instruction
+    fn load_i_long(&mut self) -> Result<PCState, CpuError> {
+        self.i = self.opcode_at(self.pc + OPCODE_SIZE)? as usize;
+        Ok(PCState::Jump(self.pc + 2 * OPCODE_SIZE))
     }
 
-    fn jump_with_addition(&mut self, nnn: usize) -> PCState {
-        PCState::Jump(nnn + self.v_registers[0] as usize)
+    fn select_planes(&mut self, x: usize) -> Result<PCState, CpuError> {
+        self.planes = x as u8 & ALL_PLANES;
+        Ok(PCState::Next)
     }
 
-    fn gen_rand(&mut self, x: usize, kk: u8) -> PCState {
+    fn jump_with_addition(&mut self, x: usize, nnn: usize) -> Result<PCState, CpuError> {
+        let offset = if self.quirks.jump_uses_vx { self.v_registers[x] } else { self.v_registers[0] };
+        Ok(PCState::Jump(nnn + offset as usize))
+    }
+
+    fn gen_rand(&mut self, x: usize, kk: u8) -> Result<PCState, CpuError> {
         let mut rng = rand::thread_rng();
         self.v_registers[x] = rng.gen::<u8>() & kk;
-        PCState::Next
+        Ok(PCState::Next)
     }
 
-    fn draw_sprite(&mut self, x: usize, y: usize, n: usize) -> PCState {
+    fn draw_sprite(&mut self, x: usize, y: usize, n: usize) -> Result<PCState, CpuError> {
+        // hold the draw until the next frame starts, re-running this instruction until then
+        if self.quirks.display_wait {
+            if !self.vblank_ready {
+                return Ok(PCState::Jump(self.pc));
+            }
+            self.vblank_ready = false;
+        }
+
+        // the starting position always wraps, only the pixels that run off the edge get clipped.
+        // a height of 0 draws a 16x16 SUPER-CHIP sprite made of two bytes per row
+        let (width, height) = (self.display.width(), self.display.height());
+        let start_x = self.v_registers[x] as usize % width;
+        let start_y = self.v_registers[y] as usize % height;
+        let (rows, bytes_per_row) = if n == 0 { (16, 2) } else { (n, 1) };
+        // with several XO-CHIP planes selected the sprite data for each plane follows the previous one
+        let selected_planes = self.planes;
+        let mut sprite = [0u8; 32 * PLANES];
+        let sprite_len = selected_planes.count_ones() as usize * rows * bytes_per_row;
+        sprite[..sprite_len].copy_from_slice(self.read_memory(self.i, sprite_len)?);
         self.v_registers[0xF] = 0;
-        for byte in 0..n {
-            let y = (self.v_registers[y] as usize + byte) % CHIP8_HEIGHT;
-            for bit in 0..8 {
-                let x = (self.v_registers[x] as usize + bit) % CHIP8_WIDTH;
-                let color = (self.ram[self.i + byte] >> (7 - bit) as u8) & 1;
-                self.v_registers[0xF] |= color & self.display[y][x];
-                self.display[y][x] ^= color;
+        let mut sprite_offset = 0;
+        for plane in (0..8).map(|bit| 1u8 << bit).filter(|plane| selected_planes & plane != 0) {
+            for row in 0..rows {
+                if self.quirks.clip_sprites && start_y + row >= height {
+                    break;
+                }
+                let y = (start_y + row) % height;
+                for bit in 0..8 * bytes_per_row {
+                    if self.quirks.clip_sprites && start_x + bit >= width {
+                        break;
+                    }
+                    let x = (start_x + bit) % width;
+                    let byte = sprite[sprite_offset + row * bytes_per_row + bit / 8];
+                    if (byte >> (7 - bit % 8) as u8) & 1 == 1 && self.display.toggle(x, y, plane) {
+                        self.v_registers[0xF] = 1;
+                    }
+                }
             }
+            sprite_offset += rows * bytes_per_row;
         }
         self.display_update = true;
-        PCState::Next
+        Ok(PCState::Next)
     }
 
-    fn load_delay_timer_to_register(&mut self, x: usize) -> PCState {
+    fn load_delay_timer_to_register(&mut self, x: usize) -> Result<PCState, CpuError> {
         self.v_registers[x] = self.delay_timer;
-        PCState::Next
+        Ok(PCState::Next)
     }
 
-    fn wait_for_keypress(&mut self, x: usize) -> PCState {
+    fn wait_for_keypress(&mut self, x: usize) -> Result<PCState, CpuError> {
         self.keypad_waiting = true;
         self.keypad_register = x;
-        PCState::Next
+        Ok(PCState::Next)
     }
 
-    fn set_delay_timer(&mut self, x: usize) -> PCState {
+    fn set_delay_timer(&mut self, x: usize) -> Result<PCState, CpuError> {
         self.delay_timer = self.v_registers[x];
-        PCState::Next
+        Ok(PCState::Next)
     }
 
-    fn set_sound_timer(&mut self, x: usize) -> PCState {
+    fn set_sound_timer(&mut self, x: usize) -> Result<PCState, CpuError> {
         self.sound_timer = self.v_registers[x];
-        PCState::Next
+        Ok(PCState::Next)
     }
 
-    fn add_register_to_i(&mut self, x: usize) -> PCState {
-        self.i += self.v_registers[x] as usize;
-        self.v_registers[0xF] = if self.i > 0x0F00 { 1 } else { 0 };
-        PCState::Next
+    // F002 loads the 128 bit XO-CHIP audio pattern from memory at I
+    fn load_audio_pattern(&mut self) -> Result<PCState, CpuError> {
+        let mut pattern = [0u8; 16];
+        pattern.copy_from_slice(self.read_memory(self.i, 16)?);
+        self.audio_pattern = Some(pattern);
+        Ok(PCState::Next)
     }
 
-    fn set_i_to_sprite(&mut self, x: usize) -> PCState {
+    fn set_pitch(&mut self, x: usize) -> Result<PCState, CpuError> {
+        self.pitch = self.v_registers[x];
+        Ok(PCState::Next)
+    }
+
+    // I wraps around the address space. VF is left alone, with 64 KiB of XO-CHIP memory an I past
+    // 0xF00 is nothing unusual
+    fn add_register_to_i(&mut self, x: usize) -> Result<PCState, CpuError> {
+        self.i = (self.i + self.v_registers[x] as usize) % CHIP8_RAM;
+        Ok(PCState::Next)
+    }
+
+    fn set_i_to_sprite(&mut self, x: usize) -> Result<PCState, CpuError> {
         self.i = (self.v_registers[x] as usize) * 5;
-        PCState::Next
+        Ok(PCState::Next)
     }
 
-    fn spread_decimal(&mut self, x: usize) -> PCState {
-        self.ram[self.i] = self.v_registers[x] / 100;
-        self.ram[self.i + 1] = (self.v_registers[x] % 100) / 10;
-        self.ram[self.i + 2] = self.v_registers[x] % 10;
-        PCState::Next
+    fn set_i_to_big_sprite(&mut self, x: usize) -> Result<PCState, CpuError> {
+        self.i = BIG_FONT_ADDR + (self.v_registers[x] as usize & 0xF) * 10;
+        Ok(PCState::Next)
     }
 
-    fn dump_registers_to_mem(&mut self, x: usize) -> PCState {
-        for i in 0..x + 1 {
-            self.ram[self.i + i] = self.v_registers[i];
-        }
-        PCState::Next
+    fn spread_decimal(&mut self, x: usize) -> Result<PCState, CpuError> {
+        let vx = self.v_registers[x];
+        self.write_memory(self.i, &[vx / 100, (vx % 100) / 10, vx % 10])?;
+        Ok(PCState::Next)
+    }
+
+    fn dump_registers_to_mem(&mut self, x: usize) -> Result<PCState, CpuError> {
+        let registers = self.v_registers;
+        self.write_memory(self.i, &registers[..x + 1])?;
+        self.increment_after_load_store(x);
+        Ok(PCState::Next)
+    }
+
+    fn load_registers_from_mem(&mut self, x: usize) -> Result<PCState, CpuError> {
+        let mut registers = self.v_registers;
+        registers[..x + 1].copy_from_slice(self.read_memory(self.i, x + 1)?);
+        self.v_registers = registers;
+        self.increment_after_load_store(x);
+        Ok(PCState::Next)
     }
 
-    fn load_registers_from_mem(&mut self, x: usize) -> PCState{
-        for i in 0..x + 1 {
-            self.v_registers[i] = self.ram[self.i + i];
+    fn increment_after_load_store(&mut self, x: usize) {
+        match self.quirks.load_store_increment {
+            IndexIncrement::Unchanged => {}
+            IndexIncrement::ByX => self.i += x,
+            IndexIncrement::ByXPlusOne => self.i += x + 1,
         }
-        PCState::Next
+    }
+
+    // the RPL user flags survive between programs on the HP48, here they only live as long as the CPU
+    fn save_flags(&mut self, x: usize) -> Result<PCState, CpuError> {
+        self.rpl_flags[..x + 1].copy_from_slice(&self.v_registers[..x + 1]);
+        Ok(PCState::Next)
+    }
+
+    fn load_flags(&mut self, x: usize) -> Result<PCState, CpuError> {
+        self.v_registers[..x + 1].copy_from_slice(&self.rpl_flags[..x + 1]);
+        Ok(PCState::Next)
     }
 
     // read the next opcode from the byte array
-    fn get_opcode(&self) -> u16{
-        (self.ram[self.pc] as u16) << 8 | (self.ram[self.pc + 1]) as u16
+    fn get_opcode(&self) -> Result<u16, CpuError>{
+        self.opcode_at(self.pc)
+    }
+
+    fn opcode_at(&self, addr: usize) -> Result<u16, CpuError>{
+        let bytes = self.memory(addr, OPCODE_SIZE)?;
+        Ok((bytes[0] as u16) << 8 | (bytes[1]) as u16)
     }
 
     // run the opcode
-    fn run_opcode(&mut self, opcode: u16){
-        // split the opcode into each individual bytes
-        let bits = (
-            ((opcode & 0xF000) >> 12) as u8,
-            ((opcode & 0x0F00) >> 8) as u8,
-            ((opcode & 0x00F0) >> 4) as u8,
-            (opcode & 0x000F) as u8,
-        );
-
-        // note variable names are used as defined by the CPU specification
-        // found at http://www.cs.columbia.edu/~sedwards/classes/2016/4840-spring/designs/Chip8.pdf
-        let nnn = (opcode & 0x0FFF) as usize;
-        let kk = (opcode & 0x00FF) as u8;
-        let x = bits.1 as usize;
-        let y = bits.2 as usize;
-        let n = bits.3 as usize;
-
-        // map each opcode to the given function providing the required parameters as specified
-        // by the CPU specs
-        let pc_change = match bits {
-            (0x0, 0x0, 0xE, 0x0) => self.clear_screen(),
-            (0x0, 0x0, 0xE, 0xE) => self.ret(),
-            (0x1, _, _, _) => self.jump(nnn),
-            (0x2, _, _, _) => self.call(nnn),
-            (0x3, _, _, _) => self.skip_if_equal_register(x, kk),
-            (0x4, _, _, _) => self.skip_if_not_equal_register(x, kk),
-            (0x5, _, _, 0x0) => self.skip_if_registers_equal(x, y),
-            (0x6, _, _, _) => self.load_register(x, kk),
-            (0x7, _, _, _) => self.add_register(x, kk),
-            (0x8, _, _, 0x0) => self.load_register_into_register(x, y),
-            (0x8, _, _, 0x1) => self.or_register_by_register(x, y),
-            (0x8, _, _, 0x2) => self.and_register_by_register(x, y),
-            (0x8, _, _, 0x3) => self.xor_register_by_register(x, y),
-            (0x8, _, _, 0x4) => self.add_registers_and_carry(x, y),
-            (0x8, _, _, 0x5) => self.subtract_and_carry(x, y),
-            (0x8, _, _, 0x6) => self.shift_right(x),
-            (0x8, _, _, 0x7) => self.subtract_and_carry_inverted(x, y),
-            (0x8, _, _, 0xE) => self.shift_left(x),
-            (0x9, _, _, 0x0) => self.skip_if_registers_not_equal(x, y),
-            (0xA, _, _, _) => self.load_i(nnn),
-            (0xB, _, _, _) => self.jump_with_addition(nnn),
-            (0xC, _, _, _) => self.gen_rand(x, kk),
-            (0xD, _, _, _) => self.draw_sprite(x, y, n),
-            (0xE, _, 0x9, 0xE) => self.skip_if_key_pressed(x),
-            (0xE, _, 0xA, 0x1) => self.skip_if_key_not_pressed(x),
-            (0xF, _, 0x0, 0x7) => self.load_delay_timer_to_register(x),
-            (0xF, _, 0x0, 0xA) => self.wait_for_keypress(x),
-            (0xF, _, 0x1, 0x5) => self.set_delay_timer(x),
-            (0xF, _, 0x1, 0x8) => self.set_sound_timer(x),
-            (0xF, _, 0x1, 0xE) => self.add_register_to_i(x),
-            (0xF, _, 0x2, 0x9) => self.set_i_to_sprite(x),
-            (0xF, _, 0x3, 0x3) => self.spread_decimal(x),
-            (0xF, _, 0x5, 0x5) => self.dump_registers_to_mem(x),
-            (0xF, _, 0x6, 0x5) => self.load_registers_from_mem(x),
-            _ => {eprintln!("Invalid opcode {:x}", opcode); PCState::Next}
+    fn run_opcode(&mut self, opcode: u16) -> Result<(), CpuError>{
+        let instruction = match Instruction::decode(opcode) {
+            Some(instruction) => instruction,
+            None => return Err(CpuError::InvalidOpcode { pc: self.pc, opcode }),
         };
 
+        // map each instruction to the given function providing the required parameters as specified
+        // by the CPU specs
+        let pc_change = match instruction {
+            Instruction::ScrollDown(n) => self.scroll_down(n as usize),
+            Instruction::ScrollUp(n) => self.scroll_up(n as usize),
+            Instruction::ClearScreen => self.clear_screen(),
+            Instruction::Return => self.ret(),
+            Instruction::ScrollRight => self.scroll_right(),
+            Instruction::ScrollLeft => self.scroll_left(),
+            Instruction::Exit => self.exit(),
+            Instruction::Lores => self.set_hires(false),
+            Instruction::Hires => self.set_hires(true),
+            Instruction::Jump(nnn) => self.jump(nnn as usize),
+            Instruction::Call(nnn) => self.call(nnn as usize),
+            Instruction::SkipIfEqual(x, kk) => self.skip_if_equal_register(x as usize, kk),
+            Instruction::SkipIfNotEqual(x, kk) => self.skip_if_not_equal_register(x as usize, kk),
+            Instruction::SkipIfRegistersEqual(x, y) => self.skip_if_registers_equal(x as usize, y as usize),
+            Instruction::SaveRange(x, y) => self.save_register_range(x as usize, y as usize),
+            Instruction::LoadRange(x, y) => self.load_register_range(x as usize, y as usize),
+            Instruction::Load(x, kk) => self.load_register(x as usize, kk),
+            Instruction::Add(x, kk) => self.add_register(x as usize, kk),
+            Instruction::LoadRegister(x, y) => self.load_register_into_register(x as usize, y as usize),
+            Instruction::Or(x, y) => self.or_register_by_register(x as usize, y as usize),
+            Instruction::And(x, y) => self.and_register_by_register(x as usize, y as usize),
+            Instruction::Xor(x, y) => self.xor_register_by_register(x as usize, y as usize),
+            Instruction::AddRegisters(x, y) => self.add_registers_and_carry(x as usize, y as usize),
+            Instruction::Subtract(x, y) => self.subtract_and_carry(x as usize, y as usize),
+            Instruction::ShiftRight(x, y) => self.shift_right(x as usize, y as usize),
+            Instruction::SubtractInverted(x, y) => self.subtract_and_carry_inverted(x as usize, y as usize),
+            Instruction::ShiftLeft(x, y) => self.shift_left(x as usize, y as usize),
+            Instruction::SkipIfRegistersNotEqual(x, y) => self.skip_if_registers_not_equal(x as usize, y as usize),
+            Instruction::LoadI(nnn) => self.load_i(nnn as usize),
+            // the register for the CHIP-48 quirk is the top nibble of the address
+            Instruction::JumpWithOffset(nnn) => self.jump_with_addition((nnn >> 8) as usize, nnn as usize),
+            Instruction::Random(x, kk) => self.gen_rand(x as usize, kk),
+            Instruction::Draw(x, y, n) => self.draw_sprite(x as usize, y as usize, n as usize),
+            Instruction::SkipIfKeyPressed(x) => self.skip_if_key_pressed(x as usize),
+            Instruction::SkipIfKeyNotPressed(x) => self.skip_if_key_not_pressed(x as usize),
+            Instruction::LoadILong => self.load_i_long(),
+            Instruction::SelectPlanes(x) => self.select_planes(x as usize),
+            Instruction::LoadAudioPattern => self.load_audio_pattern(),
+            Instruction::LoadDelayTimer(x) => self.load_delay_timer_to_register(x as usize),
+            Instruction::WaitForKey(x) => self.wait_for_keypress(x as usize),
+            Instruction::SetDelayTimer(x) => self.set_delay_timer(x as usize),
+            Instruction::SetSoundTimer(x) => self.set_sound_timer(x as usize),
+            Instruction::AddToI(x) => self.add_register_to_i(x as usize),
+            Instruction::LoadFont(x) => self.set_i_to_sprite(x as usize),
+            Instruction::LoadBigFont(x) => self.set_i_to_big_sprite(x as usize),
+            Instruction::StoreDecimal(x) => self.spread_decimal(x as usize),
+            Instruction::SetPitch(x) => self.set_pitch(x as usize),
+            Instruction::StoreRegisters(x) => self.dump_registers_to_mem(x as usize),
+            Instruction::LoadRegisters(x) => self.load_registers_from_mem(x as usize),
+            Instruction::SaveFlags(x) => self.save_flags(x as usize),
+            Instruction::LoadFlags(x) => self.load_flags(x as usize),
+        }?;
+
         // based on how the program counter should change modify the PC
         match pc_change {
             PCState::Next => self.pc += OPCODE_SIZE,
+            // skipping over the 4 byte F000 NNNN instruction has to skip both of its words
+            PCState::Skip if self.opcode_at(self.pc + OPCODE_SIZE) == Ok(0xF000) => self.pc += 3 * OPCODE_SIZE,
             PCState::Skip => self.pc += 2 * OPCODE_SIZE,
             PCState::Jump(addr) => self.pc = addr,
         }
+        Ok(())
     }
 
     // main function for a CPU "tick" or operation
-    pub fn tick(&mut self, keyboard: [bool; 16]) -> CpuState{
+    pub fn tick(&mut self, keyboard: [bool; 16]) -> Result<CpuState<'_>, CpuError>{
         self.keypad = keyboard;
         self.display_update = false;
         // if we are waiting for a keypress then you know wait
@@ -453,23 +872,111 @@ impl CPU{
             }
         }
         else{
-            // decrement our counters if required
-            if self.delay_timer > 0{
-                self.delay_timer -= 1;
-            }
-            if self.sound_timer > 0{
-                self.sound_timer -= 1;
-            }
             // get our opcode and run it
-            let opcode = self.get_opcode();
-            self.run_opcode(opcode);
+            let opcode = self.get_opcode()?;
+            self.run_opcode(opcode)?;
         }
 
         // return our CPU state to tell the main loop what updates are required
+        Ok(self.state())
+    }
+
+    // run a single 60Hz frame, executing the given number of instructions before ticking the timers.
+    // the returned state reports a display update if any of the instructions changed the display.
+    // an error ends the frame early without ticking the timers
+    pub fn run_frame(&mut self, keyboard: [bool; 16], instructions: usize) -> Result<CpuState<'_>, CpuError>{
+        self.run_frame_with(keyboard, instructions, |_| {})
+    }
+
+    // run a frame calling the given function before every instruction, used by the debugger to
+    // stop on breakpoints
+    pub fn run_frame_with<F: FnMut(&mut CPU)>(&mut self, keyboard: [bool; 16], instructions: usize,
+                                              mut before_instruction: F) -> Result<CpuState<'_>, CpuError>{
+        let mut display_updated = false;
+        for _ in 0..instructions {
+            before_instruction(self);
+            display_updated |= self.tick(keyboard)?.display_updated;
+            if self.exited {
+                break;
+            }
+        }
+        self.vblank();
+
+        let mut state = self.state();
+        state.display_updated = display_updated;
+        Ok(state)
+    }
+
+    fn state(&self) -> CpuState<'_>{
         CpuState {
             display: &self.display,
             display_updated: self.display_update,
             play_sound: self.sound_timer > 0,
+            exited: self.exited,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::QuirkProfile;
+
+    // run the program until it falls off its end and hand back the CPU
+    fn run(program: &[u8], quirks: Quirks) -> CPU {
+        let mut cpu = CPU::new(quirks);
+        cpu.load(program);
+        while cpu.pc() < PROGRAM_START + program.len() {
+            cpu.tick([false; 16]).unwrap();
+        }
+        cpu
+    }
+
+    #[test]
+    fn add_to_i_leaves_vf_alone() {
+        // i := long 0x2000, v0 := 1, vF := 7, i += v0
+        let cpu = run(&[0xF0, 0x00, 0x20, 0x00, 0x60, 0x01, 0x6F, 0x07, 0xF0, 0x1E], QuirkProfile::XoChip.quirks());
+        assert_eq!(cpu.i(), 0x2001);
+        assert_eq!(cpu.v_registers()[0xF], 7);
+        // i := long 0xFFFF, v0 := 2, i += v0 wraps around
+        let cpu = run(&[0xF0, 0x00, 0xFF, 0xFF, 0x60, 0x02, 0xF0, 0x1E], QuirkProfile::XoChip.quirks());
+        assert_eq!(cpu.i(), 0x0001);
+    }
+
+    #[test]
+    fn subtract_sets_vf_when_there_is_no_borrow() {
+        // v0 := 5, v1 := 3, v0 -= v1
+        let cpu = run(&[0x60, 0x05, 0x61, 0x03, 0x80, 0x15], Quirks::default());
+        assert_eq!(cpu.v_registers()[0], 2);
+        assert_eq!(cpu.v_registers()[0xF], 1);
+        // v0 := 3, v1 := 5, v0 -= v1
+        let cpu = run(&[0x60, 0x03, 0x61, 0x05, 0x80, 0x15], Quirks::default());
+        assert_eq!(cpu.v_registers()[0], 0xFE);
+        assert_eq!(cpu.v_registers()[0xF], 0);
+    }
+
+    #[test]
+    fn subtracting_equal_registers_does_not_borrow() {
+        // v0 := 4, v1 := 4, v0 -= v1 and v0 =- v1
+        let cpu = run(&[0x60, 0x04, 0x61, 0x04, 0x80, 0x15], Quirks::default());
+        assert_eq!(cpu.v_registers()[0], 0);
+        assert_eq!(cpu.v_registers()[0xF], 1);
+        let cpu = run(&[0x60, 0x04, 0x61, 0x04, 0x80, 0x17], Quirks::default());
+        assert_eq!(cpu.v_registers()[0], 0);
+        assert_eq!(cpu.v_registers()[0xF], 1);
+    }
+
+    #[test]
+    fn subtract_into_vf_keeps_the_flag() {
+        // vF := 5, v1 := 3, vF -= v1 leaves the flag rather than the difference in vF
+        let cpu = run(&[0x6F, 0x05, 0x61, 0x03, 0x8F, 0x15], QuirkProfile::CosmacVip.quirks());
+        assert_eq!(cpu.v_registers()[0xF], 1);
+        // vF := 3, v1 := 5, vF -= v1
+        let cpu = run(&[0x6F, 0x03, 0x61, 0x05, 0x8F, 0x15], QuirkProfile::CosmacVip.quirks());
+        assert_eq!(cpu.v_registers()[0xF], 0);
+        // vF := 3, v1 := 5, vF =- v1
+        let cpu = run(&[0x6F, 0x03, 0x61, 0x05, 0x8F, 0x17], QuirkProfile::CosmacVip.quirks());
+        assert_eq!(cpu.v_registers()[0xF], 1);
+    }
+}
@@ -0,0 +1,399 @@
+use std::io::{self, BufRead, Write};
+use crate::cpu::{CpuError, CPU};
+use crate::disassembler::{self, Labels, Syntax};
+use crate::instruction::Instruction;
+use crate::watchpoint::Watchpoint;
+
+const HELP: &str = "\
+commands:
+  s, step [n]          run n instructions (default 1) then stop
+  c, continue          run until the next breakpoint
+  b, break <addr>      stop when the PC reaches addr
+  bo <pattern>         stop on opcodes matching a pattern of hex digits and wildcards, e.g. Dxyn or 8??E
+  bl, breakpoints      list breakpoints
+  bd <index>           delete a breakpoint
+  w <addr> [len]       stop after len bytes (default 1) from addr are written
+  rw <addr> [len]      stop after len bytes (default 1) from addr are read
+  aw <addr> [len]      stop after len bytes (default 1) from addr are read or written
+  wr <register>        stop after a V register changes value, e.g. wr v3
+  wl, watches          list watchpoints
+  wd <index>           delete a watchpoint
+  r, regs              print the registers, stack and timers
+  m, mem <addr> [len]  dump len bytes (default 64) of memory from addr
+  d, dis [count]       disassemble count instructions (default 16) around the PC
+  skip                 move past the instruction at the PC without running it
+  q, quit              exit the emulator
+  h, help              print this message
+addresses are hex, counts are decimal";
+
+// an opcode breakpoint matches when opcode & mask == value, wildcard nibbles are left out of the mask
+#[derive(Clone, Copy)]
+enum Breakpoint {
+    Address(usize),
+    Opcode { mask: u16, value: u16, pattern: [char; 4] },
+}
+
+impl Breakpoint {
+    // parse a 4 character opcode pattern, any character that isn't a hex digit is a wildcard
+    fn opcode(pattern: &str) -> Option<Breakpoint> {
+        let chars: Vec<char> = pattern.chars().collect();
+        if chars.len() != 4 {
+            return None;
+        }
+        let (mut mask, mut value) = (0u16, 0u16);
+        for &c in chars.iter() {
+            mask <<= 4;
+            value <<= 4;
+            if let Some(digit) = c.to_digit(16) {
+                mask |= 0xF;
+                value |= digit as u16;
+            }
+        }
+        Some(Breakpoint::Opcode { mask, value, pattern: [chars[0], chars[1], chars[2], chars[3]] })
+    }
+
+    fn hit(&self, pc: usize, opcode: u16) -> bool {
+        match *self {
+            Breakpoint::Address(addr) => pc == addr,
+            Breakpoint::Opcode { mask, value, .. } => opcode & mask == value,
+        }
+    }
+}
+
+// memory watchpoints are checked by the CPU as it accesses memory, register watches are checked by
+// comparing the registers before every instruction
+#[derive(Clone, Copy)]
+enum Watch {
+    Memory(Watchpoint),
+    Register(usize),
+}
+
+// an interactive debugger reading commands from stdin. while paused the emulator stops dead, the
+// window isn't redrawn until execution carries on
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    paused: bool,
+    steps_remaining: usize,
+    resumed_at: Option<usize>,
+    watches: Vec<Watch>,
+    registers: [u8; 16],
+    quit: bool,
+}
+
+impl Debugger {
+    // the debugger starts paused so breakpoints can be set before the program runs
+    pub fn new(paused: bool) -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            paused,
+            steps_remaining: 0,
+            resumed_at: None,
+            watches: Vec::new(),
+            registers: [0; 16],
+            quit: false,
+        }
+    }
+
+    // stop before the next instruction
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn quit_requested(&self) -> bool {
+        self.quit
+    }
+
+    // report an error from the CPU and stop on the instruction that caused it
+    pub fn report_error(&mut self, error: CpuError) {
+        println!("{}", error);
+        self.pause();
+    }
+
+    // called before every instruction, drops into the prompt when paused, at the end of a step or
+    // on a breakpoint
+    pub fn before_instruction(&mut self, cpu: &mut CPU) {
+        if self.quit {
+            return;
+        }
+        // watchpoints hit by the last instruction stop us even in the middle of a step
+        if !self.check_watches(cpu) {
+            // nothing runs while the CPU waits for a key so there is nothing to stop on
+            if cpu.keypad_waiting() {
+                return;
+            }
+            // instructions left to run before stopping again are run without checking breakpoints
+            if self.steps_remaining > 0 {
+                self.steps_remaining -= 1;
+                return;
+            }
+            let opcode = opcode_at(cpu, cpu.pc());
+            if !self.paused {
+                // don't stop straight away on the breakpoint we continued from, the PC has to move first
+                if self.resumed_at == Some(cpu.pc()) {
+                    return;
+                }
+                self.resumed_at = None;
+                match self.breakpoints.iter().position(|breakpoint| breakpoint.hit(cpu.pc(), opcode)) {
+                    Some(index) => println!("Breakpoint {} hit", index),
+                    None => return,
+                }
+            }
+        }
+        self.steps_remaining = 0;
+        self.paused = true;
+        self.prompt(cpu);
+    }
+
+    // report watched memory accesses and register changes since the last instruction, returning
+    // true if there were any
+    fn check_watches(&mut self, cpu: &mut CPU) -> bool {
+        let mut triggered = false;
+        for hit in cpu.take_watch_hits() {
+            println!("Watchpoint: {:#06x} {} {:#04x} at {:#06x}", hit.addr,
+                     if hit.write { "written with" } else { "read as" }, hit.value, hit.pc);
+            triggered = true;
+        }
+        if self.watches.iter().any(|watch| matches!(watch, Watch::Register(_))) {
+            let registers = *cpu.v_registers();
+            for watch in self.watches.iter() {
+                if let Watch::Register(register) = *watch {
+                    if registers[register] != self.registers[register] {
+                        println!("Watchpoint: V{:X} changed from {:#04x} to {:#04x}", register,
+                                 self.registers[register], registers[register]);
+                        triggered = true;
+                    }
+                }
+            }
+            self.registers = registers;
+        }
+        triggered
+    }
+
+    // hand the memory watchpoints over to the CPU, which does the actual tracking
+    fn sync_watchpoints(&mut self, cpu: &mut CPU) {
+        let watchpoints = self.watches.iter().filter_map(|watch| match *watch {
+            Watch::Memory(watchpoint) => Some(watchpoint),
+            Watch::Register(_) => None,
+        }).collect();
+        cpu.set_watchpoints(watchpoints);
+        self.registers = *cpu.v_registers();
+    }
+
+    // read and run commands until one of them resumes execution
+    fn prompt(&mut self, cpu: &mut CPU) {
+        println!("{}", disassemble_line(cpu, cpu.pc()));
+        let stdin = io::stdin();
+        loop {
+            print!("(chip8) ");
+            let _ = io::stdout().flush();
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                // stdin was closed, there is nobody left to debug
+                self.quit = true;
+                return;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            match self.run_command(cpu, &words) {
+                Ok(true) => return,
+                Ok(false) => {}
+                Err(message) => println!("{}", message),
+            }
+        }
+    }
+
+    // run a single command returning true if execution should carry on
+    fn run_command(&mut self, cpu: &mut CPU, words: &[&str]) -> Result<bool, String> {
+        match words[0] {
+            "s" | "step" => {
+                // the instruction we are stopped on is the first of the steps
+                self.steps_remaining = parse_count(words.get(1), 1)?.max(1) - 1;
+                Ok(true)
+            }
+            "c" | "continue" => {
+                self.resumed_at = Some(cpu.pc());
+                self.paused = false;
+                Ok(true)
+            }
+            "b" | "break" => {
+                let addr = parse_addr(words.get(1))?;
+                self.breakpoints.push(Breakpoint::Address(addr));
+                println!("Breakpoint {} at {:#05x}", self.breakpoints.len() - 1, addr);
+                Ok(false)
+            }
+            "bo" => {
+                let pattern = words.get(1).ok_or("Expected an opcode pattern")?;
+                let breakpoint = Breakpoint::opcode(pattern).ok_or("Opcode patterns are 4 characters long")?;
+                self.breakpoints.push(breakpoint);
+                println!("Breakpoint {} on opcode {}", self.breakpoints.len() - 1, pattern);
+                Ok(false)
+            }
+            "bl" | "breakpoints" => {
+                for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+                    match breakpoint {
+                        Breakpoint::Address(addr) => println!("{}: address {:#05x}", index, addr),
+                        Breakpoint::Opcode { pattern, .. } =>
+                            println!("{}: opcode {}", index, pattern.iter().collect::<String>()),
+                    }
+                }
+                Ok(false)
+            }
+            "bd" => {
+                let index = parse_count(words.get(1), usize::MAX)?;
+                if index >= self.breakpoints.len() {
+                    return Err(format!("No breakpoint {}", index));
+                }
+                self.breakpoints.remove(index);
+                Ok(false)
+            }
+            "w" | "rw" | "aw" => {
+                let addr = parse_addr(words.get(1))?;
+                let len = parse_count(words.get(2), 1)?;
+                if len == 0 {
+                    return Err("A watchpoint must cover at least one byte".to_string());
+                }
+                let end = addr.checked_add(len - 1).ok_or("The watchpoint goes past the end of memory")?;
+                let (reads, writes) = match words[0] {
+                    "w" => (false, true),
+                    "rw" => (true, false),
+                    _ => (true, true),
+                };
+                self.watches.push(Watch::Memory(Watchpoint { addr, len, reads, writes }));
+                self.sync_watchpoints(cpu);
+                println!("Watchpoint {} on {:#06x}-{:#06x}", self.watches.len() - 1, addr, end);
+                Ok(false)
+            }
+            "wr" => {
+                let word = words.get(1).ok_or("Expected a register")?;
+                let register = usize::from_str_radix(word.trim_start_matches(['v', 'V']), 16)
+                    .ok().filter(|&register| register < 16)
+                    .ok_or_else(|| format!("Invalid register \"{}\"", word))?;
+                self.watches.push(Watch::Register(register));
+                self.sync_watchpoints(cpu);
+                println!("Watchpoint {} on V{:X}", self.watches.len() - 1, register);
+                Ok(false)
+            }
+            "wl" | "watches" => {
+                for (index, watch) in self.watches.iter().enumerate() {
+                    match watch {
+                        Watch::Memory(watchpoint) => {
+                            let kind = match (watchpoint.reads, watchpoint.writes) {
+                                (true, true) => "access",
+                                (true, false) => "read",
+                                _ => "write",
+                            };
+                            println!("{}: {} {:#06x}-{:#06x}", index, kind, watchpoint.addr,
+                                     watchpoint.addr + watchpoint.len - 1);
+                        }
+                        Watch::Register(register) => println!("{}: register V{:X}", index, register),
+                    }
+                }
+                Ok(false)
+            }
+            "wd" => {
+                let index = parse_count(words.get(1), usize::MAX)?;
+                if index >= self.watches.len() {
+                    return Err(format!("No watchpoint {}", index));
+                }
+                self.watches.remove(index);
+                self.sync_watchpoints(cpu);
+                Ok(false)
+            }
+            "r" | "regs" => {
+                print_registers(cpu);
+                Ok(false)
+            }
+            "m" | "mem" => {
+                let addr = parse_addr(words.get(1))?;
+                let len = parse_count(words.get(2), 64)?;
+                print_memory(cpu, addr, len);
+                Ok(false)
+            }
+            "d" | "dis" => {
+                let count = parse_count(words.get(1), 16)?;
+                // start a few instructions before the PC so there is some context
+                let start = cpu.pc().saturating_sub(2 * (count / 4));
+                for addr in (start..).step_by(2).take(count) {
+                    if addr + 1 >= cpu.ram().len() {
+                        break;
+                    }
+                    println!("{}", disassemble_line(cpu, addr));
+                }
+                Ok(false)
+            }
+            "skip" => {
+                cpu.skip_instruction();
+                println!("{}", disassemble_line(cpu, cpu.pc()));
+                Ok(false)
+            }
+            "q" | "quit" => {
+                self.quit = true;
+                Ok(true)
+            }
+            "h" | "help" => {
+                println!("{}", HELP);
+                Ok(false)
+            }
+            command => Err(format!("Unknown command \"{}\", type help for a list of commands", command)),
+        }
+    }
+}
+
+fn parse_addr(word: Option<&&str>) -> Result<usize, String> {
+    let word = word.ok_or("Expected an address")?;
+    usize::from_str_radix(word.trim_start_matches("0x"), 16).map_err(|_| format!("Invalid address \"{}\"", word))
+}
+
+fn parse_count(word: Option<&&str>, default: usize) -> Result<usize, String> {
+    match word {
+        Some(word) => word.parse().map_err(|_| format!("Invalid number \"{}\"", word)),
+        None => Ok(default),
+    }
+}
+
+fn opcode_at(cpu: &CPU, addr: usize) -> u16 {
+    let ram = cpu.ram();
+    let high = ram.get(addr).copied().unwrap_or(0) as u16;
+    let low = ram.get(addr + 1).copied().unwrap_or(0) as u16;
+    high << 8 | low
+}
+
+fn print_registers(cpu: &CPU) {
+    print!("{}", register_dump(cpu));
+}
+
+// the registers, timers and stack of the CPU, a few lines of text
+pub fn register_dump(cpu: &CPU) -> String {
+    let mut dump = format!("PC {:#06x}  I {:#06x}  SP {:2}  DT {:3}  ST {:3}{}\n", cpu.pc(), cpu.i(), cpu.sp(),
+                           cpu.delay_timer(), cpu.sound_timer(), if cpu.keypad_waiting() { "  waiting for key" } else { "" });
+    for (row, registers) in cpu.v_registers().chunks(8).enumerate() {
+        let line: Vec<String> = registers.iter().enumerate()
+            .map(|(i, value)| format!("V{:X} {:02x}", row * 8 + i, value)).collect();
+        dump += &format!("{}\n", line.join("  "));
+    }
+    let stack: Vec<String> = cpu.stack().iter().map(|addr| format!("{:#06x}", addr)).collect();
+    dump += &format!("stack [{}]\n", stack.join(", "));
+    dump
+}
+
+fn print_memory(cpu: &CPU, addr: usize, len: usize) {
+    let end = addr.saturating_add(len).min(cpu.ram().len());
+    for line_start in (addr..end).step_by(16) {
+        let bytes = &cpu.ram()[line_start..(line_start + 16).min(end)];
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        println!("{:#06x}  {}", line_start, hex.join(" "));
+    }
+}
+
+// one line of disassembly with a marker on the instruction at the PC
+fn disassemble_line(cpu: &CPU, addr: usize) -> String {
+    let opcode = opcode_at(cpu, addr);
+    let marker = if addr == cpu.pc() { ">" } else { " " };
+    let text = match Instruction::decode(opcode) {
+        Some(instruction) => disassembler::mnemonic(instruction, opcode_at(cpu, addr + 2), Syntax::Cowgod, &Labels::new()),
+        None => format!("DW {:#06x}", opcode),
+    };
+    format!("{} {:#06x}  {:04x}  {}", marker, addr, opcode, text)
+}
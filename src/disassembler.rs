@@ -0,0 +1,300 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use crate::instruction::Instruction;
+
+// the number of data bytes shown on one line of a listing
+const DATA_PER_LINE: usize = 8;
+
+// Cowgod's technical reference mnemonics (LD V0, 0x12) or Octo's assembly language (v0 := 0x12)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Syntax {
+    Cowgod,
+    Octo,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Syntax, String> {
+        match name {
+            "cowgod" => Ok(Syntax::Cowgod),
+            "octo" => Ok(Syntax::Octo),
+            _ => Err(format!("unknown syntax \"{}\", expected cowgod or octo", name)),
+        }
+    }
+}
+
+// names given to addresses, anything without a name is shown as a plain address
+pub type Labels = BTreeMap<usize, String>;
+
+// render a single instruction. long is the address word following an F000 NNNN long load and is
+// ignored for every other instruction
+pub fn mnemonic(instruction: Instruction, long: u16, syntax: Syntax, labels: &Labels) -> String {
+    let addr = |addr: u16| match labels.get(&(addr as usize)) {
+        Some(label) => label.clone(),
+        None => format!("{:#05x}", addr),
+    };
+    match syntax {
+        Syntax::Cowgod => cowgod(instruction, long, addr),
+        Syntax::Octo => octo(instruction, long, addr, labels),
+    }
+}
+
+// render bytes that aren't code
+pub fn data(bytes: &[u8], syntax: Syntax) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:#04x}", byte)).collect();
+    match syntax {
+        Syntax::Cowgod => format!("DB {}", bytes.join(", ")),
+        Syntax::Octo => bytes.join(" "),
+    }
+}
+
+fn cowgod(instruction: Instruction, long: u16, addr: impl Fn(u16) -> String) -> String {
+    match instruction {
+        Instruction::ScrollDown(n) => format!("SCD {}", n),
+        Instruction::ScrollUp(n) => format!("SCU {}", n),
+        Instruction::ClearScreen => "CLS".to_string(),
+        Instruction::Return => "RET".to_string(),
+        Instruction::ScrollRight => "SCR".to_string(),
+        Instruction::ScrollLeft => "SCL".to_string(),
+        Instruction::Exit => "EXIT".to_string(),
+        Instruction::Lores => "LOW".to_string(),
+        Instruction::Hires => "HIGH".to_string(),
+        Instruction::Jump(nnn) => format!("JP {}", addr(nnn)),
+        Instruction::Call(nnn) => format!("CALL {}", addr(nnn)),
+        Instruction::SkipIfEqual(x, kk) => format!("SE V{:X}, {:#04x}", x, kk),
+        Instruction::SkipIfNotEqual(x, kk) => format!("SNE V{:X}, {:#04x}", x, kk),
+        Instruction::SkipIfRegistersEqual(x, y) => format!("SE V{:X}, V{:X}", x, y),
+        Instruction::SaveRange(x, y) => format!("SAVE V{:X}-V{:X}", x, y),
+        Instruction::LoadRange(x, y) => format!("LOAD V{:X}-V{:X}", x, y),
+        Instruction::Load(x, kk) => format!("LD V{:X}, {:#04x}", x, kk),
+        Instruction::Add(x, kk) => format!("ADD V{:X}, {:#04x}", x, kk),
+        Instruction::LoadRegister(x, y) => format!("LD V{:X}, V{:X}", x, y),
+        Instruction::Or(x, y) => format!("OR V{:X}, V{:X}", x, y),
+        Instruction::And(x, y) => format!("AND V{:X}, V{:X}", x, y),
+        Instruction::Xor(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+        Instruction::AddRegisters(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+        Instruction::Subtract(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+        Instruction::ShiftRight(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+        Instruction::SubtractInverted(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+        Instruction::ShiftLeft(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+        Instruction::SkipIfRegistersNotEqual(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+        Instruction::LoadI(nnn) => format!("LD I, {}", addr(nnn)),
+        Instruction::JumpWithOffset(nnn) => format!("JP V0, {}", addr(nnn)),
+        Instruction::Random(x, kk) => format!("RND V{:X}, {:#04x}", x, kk),
+        Instruction::Draw(x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Instruction::SkipIfKeyPressed(x) => format!("SKP V{:X}", x),
+        Instruction::SkipIfKeyNotPressed(x) => format!("SKNP V{:X}", x),
+        Instruction::LoadILong => format!("LD I, LONG {}", addr(long)),
+        Instruction::SelectPlanes(x) => format!("PLANE {}", x),
+        Instruction::LoadAudioPattern => "AUDIO".to_string(),
+        Instruction::LoadDelayTimer(x) => format!("LD V{:X}, DT", x),
+        Instruction::WaitForKey(x) => format!("LD V{:X}, K", x),
+        Instruction::SetDelayTimer(x) => format!("LD DT, V{:X}", x),
+        Instruction::SetSoundTimer(x) => format!("LD ST, V{:X}", x),
+        Instruction::AddToI(x) => format!("ADD I, V{:X}", x),
+        Instruction::LoadFont(x) => format!("LD F, V{:X}", x),
+        Instruction::LoadBigFont(x) => format!("LD HF, V{:X}", x),
+        Instruction::StoreDecimal(x) => format!("LD B, V{:X}", x),
+        Instruction::SetPitch(x) => format!("PITCH V{:X}", x),
+        Instruction::StoreRegisters(x) => format!("LD [I], V{:X}", x),
+        Instruction::LoadRegisters(x) => format!("LD V{:X}, [I]", x),
+        Instruction::SaveFlags(x) => format!("LD R, V{:X}", x),
+        Instruction::LoadFlags(x) => format!("LD V{:X}, R", x),
+    }
+}
+
+// Octo writes skips as the condition under which the next instruction runs, so every comparison
+// reads inverted compared to the opcode
+fn octo(instruction: Instruction, long: u16, addr: impl Fn(u16) -> String, labels: &Labels) -> String {
+    match instruction {
+        Instruction::ScrollDown(n) => format!("scroll-down {}", n),
+        Instruction::ScrollUp(n) => format!("scroll-up {}", n),
+        Instruction::ClearScreen => "clear".to_string(),
+        Instruction::Return => "return".to_string(),
+        Instruction::ScrollRight => "scroll-right".to_string(),
+        Instruction::ScrollLeft => "scroll-left".to_string(),
+        Instruction::Exit => "exit".to_string(),
+        Instruction::Lores => "lores".to_string(),
+        Instruction::Hires => "hires".to_string(),
+        Instruction::Jump(nnn) => format!("jump {}", addr(nnn)),
+        // a subroutine is called just by writing its name
+        Instruction::Call(nnn) => match labels.get(&(nnn as usize)) {
+            Some(label) => label.clone(),
+            None => format!(":call {:#05x}", nnn),
+        },
+        Instruction::SkipIfEqual(x, kk) => format!("if v{:x} != {:#04x} then", x, kk),
+        Instruction::SkipIfNotEqual(x, kk) => format!("if v{:x} == {:#04x} then", x, kk),
+        Instruction::SkipIfRegistersEqual(x, y) => format!("if v{:x} != v{:x} then", x, y),
+        Instruction::SaveRange(x, y) => format!("save v{:x} - v{:x}", x, y),
+        Instruction::LoadRange(x, y) => format!("load v{:x} - v{:x}", x, y),
+        Instruction::Load(x, kk) => format!("v{:x} := {:#04x}", x, kk),
+        Instruction::Add(x, kk) => format!("v{:x} += {:#04x}", x, kk),
+        Instruction::LoadRegister(x, y) => format!("v{:x} := v{:x}", x, y),
+        Instruction::Or(x, y) => format!("v{:x} |= v{:x}", x, y),
+        Instruction::And(x, y) => format!("v{:x} &= v{:x}", x, y),
+        Instruction::Xor(x, y) => format!("v{:x} ^= v{:x}", x, y),
+        Instruction::AddRegisters(x, y) => format!("v{:x} += v{:x}", x, y),
+        Instruction::Subtract(x, y) => format!("v{:x} -= v{:x}", x, y),
+        Instruction::ShiftRight(x, y) => format!("v{:x} >>= v{:x}", x, y),
+        Instruction::SubtractInverted(x, y) => format!("v{:x} =- v{:x}", x, y),
+        Instruction::ShiftLeft(x, y) => format!("v{:x} <<= v{:x}", x, y),
+        Instruction::SkipIfRegistersNotEqual(x, y) => format!("if v{:x} == v{:x} then", x, y),
+        Instruction::LoadI(nnn) => format!("i := {}", addr(nnn)),
+        Instruction::JumpWithOffset(nnn) => format!("jump0 {}", addr(nnn)),
+        Instruction::Random(x, kk) => format!("v{:x} := random {:#04x}", x, kk),
+        Instruction::Draw(x, y, n) => format!("sprite v{:x} v{:x} {}", x, y, n),
+        Instruction::SkipIfKeyPressed(x) => format!("if v{:x} -key then", x),
+        Instruction::SkipIfKeyNotPressed(x) => format!("if v{:x} key then", x),
+        Instruction::LoadILong => format!("i := long {}", addr(long)),
+        Instruction::SelectPlanes(x) => format!("plane {}", x),
+        Instruction::LoadAudioPattern => "audio".to_string(),
+        Instruction::LoadDelayTimer(x) => format!("v{:x} := delay", x),
+        Instruction::WaitForKey(x) => format!("v{:x} := key", x),
+        Instruction::SetDelayTimer(x) => format!("delay := v{:x}", x),
+        Instruction::SetSoundTimer(x) => format!("buzzer := v{:x}", x),
+        Instruction::AddToI(x) => format!("i += v{:x}", x),
+        Instruction::LoadFont(x) => format!("i := hex v{:x}", x),
+        Instruction::LoadBigFont(x) => format!("i := bighex v{:x}", x),
+        Instruction::StoreDecimal(x) => format!("bcd v{:x}", x),
+        Instruction::SetPitch(x) => format!("pitch := v{:x}", x),
+        Instruction::StoreRegisters(x) => format!("save v{:x}", x),
+        Instruction::LoadRegisters(x) => format!("load v{:x}", x),
+        Instruction::SaveFlags(x) => format!("saveflags v{:x}", x),
+        Instruction::LoadFlags(x) => format!("loadflags v{:x}", x),
+    }
+}
+
+// a ROM split into code and data. the code is found by following every path the program can take
+// from its first instruction, anything never reached is assumed to be data. jumps through V0 can't
+// be followed exactly so only the base of the jump table is treated as code
+pub struct Disassembly<'a> {
+    rom: &'a [u8],
+    origin: usize,
+    instructions: BTreeMap<usize, Instruction>,
+    labels: Labels,
+}
+
+impl<'a> Disassembly<'a> {
+    // analyse a ROM loaded at origin, which is also where execution starts
+    pub fn new(rom: &'a [u8], origin: usize) -> Disassembly<'a> {
+        let mut disassembly = Disassembly { rom, origin, instructions: BTreeMap::new(), labels: Labels::new() };
+        let mut subroutines = BTreeSet::new();
+        let mut jumps = BTreeSet::new();
+        let mut data = BTreeSet::new();
+
+        let mut pending = vec![origin];
+        while let Some(addr) = pending.pop() {
+            if disassembly.instructions.contains_key(&addr) {
+                continue;
+            }
+            let instruction = match disassembly.decode(addr) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            disassembly.instructions.insert(addr, instruction);
+            let next = addr + instruction.size();
+            match instruction {
+                Instruction::Return | Instruction::Exit => {}
+                Instruction::Jump(target) | Instruction::JumpWithOffset(target) => {
+                    jumps.insert(target as usize);
+                    pending.push(target as usize);
+                }
+                Instruction::Call(target) => {
+                    subroutines.insert(target as usize);
+                    pending.push(target as usize);
+                    pending.push(next);
+                }
+                Instruction::LoadI(target) => {
+                    data.insert(target as usize);
+                    pending.push(next);
+                }
+                Instruction::LoadILong => {
+                    data.insert(disassembly.word(addr + 2).unwrap_or(0) as usize);
+                    pending.push(next);
+                }
+                _ if instruction.is_skip() => {
+                    pending.push(next);
+                    let skipped = disassembly.decode(next).map_or(2, Instruction::size);
+                    pending.push(next + skipped);
+                }
+                _ => pending.push(next),
+            }
+        }
+
+        // subroutines take priority over jump targets which take priority over data, and only
+        // addresses inside the ROM get a name
+        let in_rom = |addr: &usize| *addr >= origin && *addr < origin + rom.len();
+        for addr in data.iter().filter(|addr| in_rom(addr)) {
+            disassembly.labels.insert(*addr, format!("data_{:04x}", addr));
+        }
+        for addr in jumps.iter().filter(|addr| in_rom(addr)) {
+            disassembly.labels.insert(*addr, format!("label_{:04x}", addr));
+        }
+        for addr in subroutines.iter().filter(|addr| in_rom(addr)) {
+            disassembly.labels.insert(*addr, format!("sub_{:04x}", addr));
+        }
+        disassembly
+    }
+
+    pub fn labels(&self) -> &Labels {
+        &self.labels
+    }
+
+    // whether the analysis found an instruction starting at addr
+    pub fn is_code(&self, addr: usize) -> bool {
+        self.instructions.contains_key(&addr)
+    }
+
+    // an annotated listing of the whole ROM, one instruction or run of data per line with its address
+    // and raw bytes. in Octo syntax the annotations are comments so the listing can be assembled again
+    pub fn listing(&self, syntax: Syntax) -> String {
+        let mut out = String::new();
+        let end = self.origin + self.rom.len();
+        let mut addr = self.origin;
+        while addr < end {
+            if let Some(label) = self.labels.get(&addr) {
+                match syntax {
+                    Syntax::Cowgod => out.push_str(&format!("{}:\n", label)),
+                    Syntax::Octo => out.push_str(&format!(": {}\n", label)),
+                }
+            }
+
+            let (text, len) = match self.instructions.get(&addr) {
+                Some(&instruction) => {
+                    let long = self.word(addr + 2).unwrap_or(0);
+                    (mnemonic(instruction, long, syntax, &self.labels), instruction.size())
+                }
+                None => {
+                    // a run of data stops at the next label or instruction
+                    let len = (addr..end).take(DATA_PER_LINE).enumerate()
+                        .take_while(|&(i, at)| i == 0 || !(self.labels.contains_key(&at) || self.is_code(at)))
+                        .count();
+                    (data(&self.rom[addr - self.origin..addr - self.origin + len], syntax), len)
+                }
+            };
+
+            let raw: Vec<String> = self.rom[addr - self.origin..addr - self.origin + len].iter()
+                .map(|byte| format!("{:02x}", byte)).collect();
+            match syntax {
+                Syntax::Cowgod => out.push_str(&format!("    {:04x}  {:<24}{}\n", addr, raw.join(" "), text)),
+                Syntax::Octo => out.push_str(&format!("    {:<40}# {:04x}  {}\n", text, addr, raw.join(" "))),
+            }
+            addr += len;
+        }
+        out
+    }
+
+    // decode the instruction at addr, instructions running off the end of the ROM aren't code
+    fn decode(&self, addr: usize) -> Option<Instruction> {
+        let instruction = Instruction::decode(self.word(addr)?)?;
+        self.word(addr + instruction.size() - 2)?;
+        Some(instruction)
+    }
+
+    fn word(&self, addr: usize) -> Option<u16> {
+        let offset = addr.checked_sub(self.origin)?;
+        let bytes = self.rom.get(offset..offset + 2)?;
+        Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+}
@@ -0,0 +1,231 @@
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+// resolution of the original CHIP8 display and of the SUPER-CHIP high resolution mode
+pub const CHIP8_WIDTH: usize = 64;
+pub const CHIP8_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+// number of XO-CHIP bitplanes, each pixel stores one bit per plane giving 4 colours
+pub const PLANES: usize = 2;
+pub const ALL_PLANES: u8 = (1 << PLANES) - 1;
+
+// an RGB colour
+pub type Colour = (u8, u8, u8);
+
+// colours indexed by the plane bitmask of a pixel: off, plane 1, plane 2 and both planes
+pub type Palette = [Colour; 1 << PLANES];
+
+// colours used unless the program asks for its own
+pub const DEFAULT_PALETTE: Palette = [(255, 255, 255), (0, 0, 0), (170, 170, 170), (85, 85, 85)];
+
+// parse a colour written as #RGB or #RRGGBB
+pub fn parse_colour(text: &str) -> Option<Colour> {
+    let hex = text.strip_prefix('#').filter(|hex| hex.is_ascii())?;
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+    match hex.len() {
+        3 => Some((channel(&hex[0..1].repeat(2))?, channel(&hex[1..2].repeat(2))?, channel(&hex[2..3].repeat(2))?)),
+        6 => Some((channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?)),
+        _ => None,
+    }
+}
+
+// the display buffer is always allocated at the high resolution size, in low resolution mode only
+// the top left CHIP8_WIDTH x CHIP8_HEIGHT pixels are used. every pixel holds a bitmask of the
+// planes it is set in, so 0 is off and 1 is on for programs that never select another plane
+#[derive(Clone)]
+pub struct Display {
+    pixels: [[u8; HIRES_WIDTH]; HIRES_HEIGHT],
+    hires: bool,
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
+}
+
+impl Display {
+    pub fn new() -> Display {
+        Display {
+            pixels: [[0; HIRES_WIDTH]; HIRES_HEIGHT],
+            hires: false,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { CHIP8_WIDTH }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { CHIP8_HEIGHT }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // switching resolution clears every plane like the SUPER-CHIP interpreters do
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.clear(ALL_PLANES);
+    }
+
+    // clear the given planes, leaving the others untouched
+    pub fn clear(&mut self, planes: u8) {
+        for row in self.pixels.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel &= !planes;
+            }
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y][x]
+    }
+
+    // the visible pixels of a row in the current resolution
+    pub fn row(&self, y: usize) -> &[u8] {
+        &self.pixels[y][..self.width()]
+    }
+
+    // XOR a pixel onto the screen, returning true if an on pixel was turned off
+    pub fn toggle(&mut self, x: usize, y: usize, colour: u8) -> bool {
+        let collision = colour & self.pixels[y][x] != 0;
+        self.pixels[y][x] ^= colour;
+        collision
+    }
+
+    // write the resolution and every pixel to a save state
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.hires);
+        for row in self.pixels.iter() {
+            writer.bytes(row);
+        }
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.hires = reader.bool()?;
+        for row in self.pixels.iter_mut() {
+            reader.fill(row)?;
+        }
+        Ok(())
+    }
+
+    // move the given planes of pixel (x, y) to the given planes of pixel (x - dx, y - dy),
+    // clearing them if the source is off the screen
+    fn shift_pixel(&mut self, x: usize, y: usize, dx: isize, dy: isize, planes: u8) {
+        let (source_x, source_y) = (x as isize - dx, y as isize - dy);
+        let source = if source_x >= 0 && source_y >= 0
+                        && (source_x as usize) < self.width() && (source_y as usize) < self.height() {
+            self.pixels[source_y as usize][source_x as usize] & planes
+        } else {
+            0
+        };
+        self.pixels[y][x] = (self.pixels[y][x] & !planes) | source;
+    }
+
+    pub fn scroll_down(&mut self, n: usize, planes: u8) {
+        let (width, height) = (self.width(), self.height());
+        for y in (0..height).rev() {
+            for x in 0..width {
+                self.shift_pixel(x, y, 0, n as isize, planes);
+            }
+        }
+    }
+
+    pub fn scroll_up(&mut self, n: usize, planes: u8) {
+        let (width, height) = (self.width(), self.height());
+        for y in 0..height {
+            for x in 0..width {
+                self.shift_pixel(x, y, 0, -(n as isize), planes);
+            }
+        }
+    }
+
+    pub fn scroll_right(&mut self, n: usize, planes: u8) {
+        let (width, height) = (self.width(), self.height());
+        for y in 0..height {
+            for x in (0..width).rev() {
+                self.shift_pixel(x, y, n as isize, 0, planes);
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self, n: usize, planes: u8) {
+        let (width, height) = (self.width(), self.height());
+        for y in 0..height {
+            for x in 0..width {
+                self.shift_pixel(x, y, -(n as isize), 0, planes);
+            }
+        }
+    }
+}
+
+// a copy of the visible part of the display handed to video backends, every pixel holds the bitmask
+// of the planes it is set in like the display does. filters that blend colours, like the phosphor
+// filter, also give every pixel its own colour to be shown in instead of the one from the palette
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    colours: Option<Vec<Colour>>,
+}
+
+impl Framebuffer {
+    pub fn new(display: &Display) -> Framebuffer {
+        let mut pixels = Vec::with_capacity(display.width() * display.height());
+        for y in 0..display.height() {
+            pixels.extend_from_slice(display.row(y));
+        }
+        Framebuffer::from_pixels(display.width(), display.height(), pixels)
+    }
+
+    // the pixels are given a row at a time, top to bottom
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<u8>) -> Framebuffer {
+        assert_eq!(pixels.len(), width * height, "framebuffer is the wrong size");
+        Framebuffer {
+            width,
+            height,
+            pixels,
+            colours: None,
+        }
+    }
+
+    // show every pixel in the given colour, a row at a time, instead of the palette's
+    pub fn with_colours(mut self, colours: Vec<Colour>) -> Framebuffer {
+        assert_eq!(colours.len(), self.pixels.len(), "framebuffer colours are the wrong size");
+        self.colours = Some(colours);
+        self
+    }
+
+    // the colour a pixel is shown in
+    pub fn colour(&self, x: usize, y: usize, palette: &Palette) -> Colour {
+        match &self.colours {
+            Some(colours) => colours[y * self.width + x],
+            None => palette[self.get(x, y) as usize % palette.len()],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn row(&self, y: usize) -> &[u8] {
+        &self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    // every pixel a row at a time, top to bottom
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+}
@@ -1,25 +1,22 @@
 use sdl2::audio::{AudioDevice, AudioCallback, AudioSpecDesired};
+use chip8_core::beeper::Beeper;
+use chip8_core::frontend::{AudioSink, Tone};
 
-struct SquareWave{
-    phase_inc: f32,
-    phase: f32,
-    volume: f32,
-}
+// the SDL audio callback, filled by the beeper from the core
+struct BeeperCallback(Beeper);
 
-impl AudioCallback for SquareWave {
+impl AudioCallback for BeeperCallback {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
-        // Generate a square wave
-        for x in out.iter_mut() {
-            *x = self.volume * if self.phase < 0.5 { 1.0 } else { -1.0 };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-        }
+        self.0.fill(out);
     }
 }
 
 pub struct AudioDriver{
-    audio: AudioDevice<SquareWave>
+    audio: AudioDevice<BeeperCallback>,
+    pattern: Option<[u8; 16]>,
+    pitch: u8,
 }
 
 impl AudioDriver{
@@ -36,14 +33,10 @@ impl AudioDriver{
                 println!("Audio Spec: {:?}", spec);
 
                 // initialize the audio callback
-                SquareWave {
-                    phase_inc: 240.0 / spec.freq as f32,
-                    phase: 0.0,
-                    volume: 0.25,
-                }
+                BeeperCallback(Beeper::new(spec.freq as u32))
             }).unwrap();
 
-        AudioDriver{audio: device}
+        AudioDriver{audio: device, pattern: None, pitch: 64}
     }
 
     pub fn start(&mut self){
@@ -53,4 +46,26 @@ impl AudioDriver{
     pub fn stop(&mut self){
         self.audio.pause();
     }
-}
\ No newline at end of file
+
+    // hand the XO-CHIP pattern and pitch to the audio callback, only locking the device when they change
+    pub fn set_pattern(&mut self, pattern: Option<[u8; 16]>, pitch: u8){
+        if pattern == self.pattern && pitch == self.pitch {
+            return;
+        }
+        self.pattern = pattern;
+        self.pitch = pitch;
+        self.audio.lock().0.set_pattern(pattern, pitch);
+    }
+}
+
+impl AudioSink for AudioDriver{
+    fn set_tone(&mut self, tone: Option<Tone>){
+        match tone {
+            Some(tone) => {
+                self.set_pattern(tone.pattern, tone.pitch);
+                self.start();
+            }
+            None => self.stop(),
+        }
+    }
+}
@@ -1,5 +1,12 @@
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+use chip8_core::frontend::{Hotkey, InputSource, InputState};
+
+// F1-F10 pick save state slots 1-10
+const SLOT_KEYS: [Keycode; 10] = [
+    Keycode::F1, Keycode::F2, Keycode::F3, Keycode::F4, Keycode::F5,
+    Keycode::F6, Keycode::F7, Keycode::F8, Keycode::F9, Keycode::F10,
+];
 
 pub struct InputDriver{
     event_pump: sdl2::EventPump
@@ -10,17 +17,44 @@ impl InputDriver{
     pub fn new(sdl_context: &sdl2::Sdl) -> InputDriver{
         InputDriver{event_pump: sdl_context.event_pump().expect("Failed to get event handler")}
     }
+}
 
-    pub fn poll(&mut self) -> Result<[bool; 16], ()>{
-        // if we get a quit event then return an error
+impl InputSource for InputDriver{
+    fn poll(&mut self) -> InputState{
+        // note any quit event and collect the hotkeys that were pressed
+        let mut hotkeys = Vec::new();
+        let mut quit = false;
+        let mut redraw = false;
         for event in self.event_pump.poll_iter(){
-            if let Event::Quit { .. } = event {
-                return Err(());
-            };
+            match event {
+                Event::Quit { .. } => quit = true,
+                // the window has been resized or uncovered so what was drawn on it is gone
+                Event::Window { win_event: WindowEvent::SizeChanged(..) | WindowEvent::Exposed, .. } => redraw = true,
+                Event::KeyDown { keycode: Some(keycode), keymod, repeat: false, .. } => {
+                    let alt = keymod.intersects(Mod::LALTMOD | Mod::RALTMOD);
+                    if keycode == Keycode::Return && alt {
+                        hotkeys.push(Hotkey::Fullscreen);
+                    } else if let Some(slot) = SLOT_KEYS.iter().position(|&key| key == keycode) {
+                        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                        hotkeys.push(if shift { Hotkey::LoadState(slot + 1) } else { Hotkey::SaveState(slot + 1) });
+                    } else if keycode == Keycode::T {
+                        hotkeys.push(Hotkey::NextTheme);
+                    } else if keycode == Keycode::P {
+                        hotkeys.push(Hotkey::Phosphor);
+                    } else if keycode == Keycode::F11 {
+                        hotkeys.push(Hotkey::Record);
+                    } else if keycode == Keycode::F12 {
+                        hotkeys.push(Hotkey::Screenshot);
+                    }
+                }
+                _ => {}
+            }
         }
 
         // get the currently pressed keys
-        let keys: Vec<Keycode> = self.event_pump.keyboard_state().pressed_scancodes()
+        let keyboard_state = self.event_pump.keyboard_state();
+        let rewind = keyboard_state.is_scancode_pressed(Scancode::Backspace);
+        let keys: Vec<Keycode> = keyboard_state.pressed_scancodes()
             .filter_map(Keycode::from_scancode).collect();
 
         // for every key if it is pressed then set the boolean in the key array to true
@@ -52,7 +86,7 @@ impl InputDriver{
         }
 
         // return the pressed key boolean array
-        Ok(chip8_keys)
+        InputState { keypad: chip8_keys, hotkeys, rewind, quit, redraw }
 
     }
 }
\ No newline at end of file
@@ -1,4 +1,7 @@
-pub mod rom_driver;
+#[cfg(feature = "sdl")]
 pub mod input_driver;
+#[cfg(feature = "sdl")]
 pub mod screen_driver;
-pub mod audio_driver;
\ No newline at end of file
+#[cfg(feature = "sdl")]
+pub mod audio_driver;
+pub mod tty_driver;
@@ -1,61 +1,149 @@
-use sdl2::render::Canvas;
-use sdl2::video::Window;
-use sdl2::pixels::Color;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{FullscreenType, Window, WindowContext};
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::Sdl;
 use sdl2::rect::Rect;
+use chip8_core::display::{Framebuffer, Palette, CHIP8_WIDTH, CHIP8_HEIGHT, DEFAULT_PALETTE, HIRES_WIDTH, HIRES_HEIGHT};
+use chip8_core::frontend::VideoSink;
 
-pub const CHIP8_WIDTH:usize = 64;
-pub const CHIP8_HEIGHT:usize = 32;
-const SCALE_FACTOR: u32 = 20;
-const SCREEN_WIDTH: u32 = (CHIP8_WIDTH as u32) * SCALE_FACTOR;
-const SCREEN_HEIGHT: u32 = (CHIP8_HEIGHT as u32) * SCALE_FACTOR;
-
-const OFF_COLOUR:(u8, u8, u8) = (255, 255, 255);
-const ON_COLOUR: (u8, u8, u8) = (0, 0, 0);
+// frames are uploaded to a texture the size of the CHIP8 display, a byte each for red, green and
+// blue, and the renderer scales it up to the window
+const BYTES_PER_PIXEL: usize = 3;
 
 pub struct ScreenDriver{
-    canvas: Canvas<Window>
+    canvas: Canvas<Window>,
+    texture_creator: TextureCreator<WindowContext>,
+    // the last frame drawn and its size, created again when the resolution changes
+    texture: Option<(Texture, usize, usize)>,
+    // whether a frame has been drawn since the window was last shown
+    dirty: bool,
+    palette: Palette
 }
 
 impl ScreenDriver{
-    pub fn new(sdl_context: &Sdl) -> ScreenDriver{
+    // open a resizable window the size of the low resolution display scaled up by the given factor
+    pub fn new(sdl_context: &Sdl, scale: u32) -> ScreenDriver{
         let video_subsystem = sdl_context.video().unwrap();
-        let window = video_subsystem.window("Chip8 Emulator by Braydn Moore",
-                                            SCREEN_WIDTH,
-                                            SCREEN_HEIGHT)
+        let mut window = video_subsystem.window("Chip8 Emulator by Braydn Moore",
+                                                (CHIP8_WIDTH as u32) * scale,
+                                                (CHIP8_HEIGHT as u32) * scale)
             .position_centered()
+            .resizable()
             .opengl()
             .build()
             .unwrap();
+        // any smaller and a high resolution pixel would be less than a pixel of the window
+        let _ = window.set_minimum_size(HIRES_WIDTH as u32, HIRES_HEIGHT as u32);
 
-        let mut canvas = window.into_canvas().build().unwrap();
+        // keep the pixels sharp when the texture is scaled up
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "nearest");
+        // showing the window waits for the monitor's next refresh so it is never shown more often
+        // than the monitor can show it
+        let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+        let texture_creator = canvas.texture_creator();
 
         canvas.set_draw_color(Color::RGB(0, 0, 0));
         canvas.clear();
         canvas.present();
 
         ScreenDriver{
-            canvas
+            canvas,
+            texture_creator,
+            texture: None,
+            dirty: false,
+            palette: DEFAULT_PALETTE
         }
     }
 
-    pub fn draw(&mut self, screen: &[[u8; CHIP8_WIDTH]; CHIP8_HEIGHT]){
-        // for every pixel in the screen array
-        for (y, row) in screen.iter().enumerate() {
-            for (x, &col) in row.iter().enumerate() {
-                // get the x y coordinates on the scaled up screen
-                let x = (x as u32) * SCALE_FACTOR;
-                let y = (y as u32) * SCALE_FACTOR;
-
-                // set the color to draw based on if the pixel is on or off
-                self.canvas.set_draw_color(if col == 0 {Color::RGB(OFF_COLOUR.0, OFF_COLOUR.1, OFF_COLOUR.2)}
-                                                else {Color::RGB(ON_COLOUR.0, ON_COLOUR.1, ON_COLOUR.2)});
-                // draw the rectangle to the canvas
-                let _ = self.canvas
-                            .fill_rect(Rect::new(x as i32, y as i32, SCALE_FACTOR, SCALE_FACTOR));
+    pub fn set_title(&mut self, title: &str){
+        let _ = self.canvas.window_mut().set_title(title);
+    }
+
+    // the texture to draw a frame of the given size into, replacing the old one if it is another size
+    fn texture(&mut self, width: usize, height: usize) -> &mut Texture{
+        if !matches!(self.texture, Some((_, w, h)) if w == width && h == height) {
+            if let Some((texture, _, _)) = self.texture.take() {
+                // textures aren't freed when dropped, the renderer frees any left when it is
+                unsafe { texture.destroy() };
             }
+            let texture = self.texture_creator
+                .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+                .expect("Failed to create the display texture");
+            self.texture = Some((texture, width, height));
         }
-        // show the window
+        &mut self.texture.as_mut().unwrap().0
+    }
+
+    // the size of a CHIP8 pixel on the window and where the display starts, the display is scaled
+    // up by the largest whole number that fits so every pixel is the same size, and centered with
+    // black bars around it
+    fn layout(&self, width: usize, height: usize) -> Rect{
+        let (window_width, window_height) = self.canvas.output_size().unwrap_or((HIRES_WIDTH as u32, HIRES_HEIGHT as u32));
+        let (width, height) = (width as u32, height as u32);
+        let scale = (window_width / width).min(window_height / height).max(1);
+        let x = (window_width as i32 - (width * scale) as i32) / 2;
+        let y = (window_height as i32 - (height * scale) as i32) / 2;
+        Rect::new(x, y, width * scale, height * scale)
+    }
+}
+
+impl VideoSink for ScreenDriver{
+    // copy the frame into the texture, it is shown on the window at the end of the frame
+    fn draw(&mut self, frame: &Framebuffer){
+        let palette = self.palette;
+        let texture = self.texture(frame.width(), frame.height());
+        let _ = texture.with_lock(None, |buffer, pitch| {
+            for y in 0..frame.height() {
+                let row = &mut buffer[y * pitch..y * pitch + frame.width() * BYTES_PER_PIXEL];
+                for (x, pixel) in row.chunks_exact_mut(BYTES_PER_PIXEL).enumerate() {
+                    // set the color to draw based on which planes the pixel is on in
+                    let (r, g, b) = frame.colour(x, y, &palette);
+                    pixel.copy_from_slice(&[r, g, b]);
+                }
+            }
+        });
+        self.dirty = true;
+    }
+
+    // scale the last frame drawn up to fit the window as it is now, clearing the bars around it
+    fn present(&mut self) -> bool{
+        if !self.dirty {
+            return false;
+        }
+        self.dirty = false;
+
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        if let Some((texture, width, height)) = self.texture.as_ref() {
+            let _ = self.canvas.copy(texture, None, self.layout(*width, *height));
+        }
+        // show the window, waiting for the monitor's next refresh
         self.canvas.present();
+        true
+    }
+
+    fn palette(&self) -> Palette{
+        self.palette
+    }
+
+    fn set_palette(&mut self, palette: Palette){
+        self.palette = palette;
+    }
+
+    fn toggle_fullscreen(&mut self){
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        let _ = window.set_fullscreen(fullscreen);
+    }
+}
+
+impl Drop for ScreenDriver{
+    fn drop(&mut self){
+        if let Some((texture, _, _)) = self.texture.take() {
+            unsafe { texture.destroy() };
+        }
     }
-}
\ No newline at end of file
+}
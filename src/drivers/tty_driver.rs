@@ -0,0 +1,273 @@
+use std::io::{self, Write};
+use std::time::Duration;
+use crossterm::{cursor, execute, queue, terminal};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+                       PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use chip8_core::display::{Colour, Framebuffer, Palette, DEFAULT_PALETTE};
+use chip8_core::frontend::{Hotkey, InputSource, InputState, VideoSink};
+
+// every character cell shows two pixels, the top one as the foreground of an upper half block and
+// the bottom one as its background
+const HALF_BLOCK: char = '\u{2580}';
+
+// most terminals only report key presses and their auto-repeats, not releases. on those a key counts
+// as held for this many frames after it was last reported, long enough to bridge the delay before
+// auto-repeat kicks in
+const HOLD_FRAMES: u32 = 30;
+
+// draws the display on the terminal with ANSI colours, only rewriting the cells that changed since
+// the last frame, with messages for the player on the line below it. the terminal is switched to
+// the alternate screen and raw mode until this is dropped, and asked to report key releases if it
+// can. everything is undone in the reverse order so the terminal is left as it was found
+pub struct TtyScreenDriver{
+    palette: Palette,
+    width: usize,
+    // the colours of the top and bottom pixel of every cell, None until it has been drawn
+    cells: Vec<Option<(Colour, Colour)>>,
+    // the size of the terminal when it was last drawn on
+    size: (u16, u16),
+    status: String,
+    // errors are printed again once the terminal is back to normal so they aren't lost
+    errors: Vec<String>,
+    // whether the terminal reports key releases
+    releases: bool,
+}
+
+impl TtyScreenDriver{
+    pub fn new() -> io::Result<TtyScreenDriver>{
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if releases {
+            execute!(io::stdout(), PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        }
+        Ok(TtyScreenDriver{
+            palette: DEFAULT_PALETTE,
+            width: 0,
+            cells: Vec::new(),
+            size: terminal::size()?,
+            status: String::new(),
+            errors: Vec::new(),
+            releases,
+        })
+    }
+
+    // whether key releases are reported, for the input driver reading keys from this terminal
+    pub fn reports_releases(&self) -> bool{
+        self.releases
+    }
+
+    fn draw_cells(&mut self, frame: &Framebuffer) -> io::Result<()>{
+        let mut stdout = io::stdout().lock();
+
+        // resizing the terminal can leave anything on it, so start again
+        let size = terminal::size()?;
+        if size != self.size {
+            self.size = size;
+            self.cells.clear();
+        }
+
+        // a change of resolution, palette or terminal size redraws the whole screen
+        let rows = frame.height() / 2;
+        if self.width != frame.width() || self.cells.len() != frame.width() * rows {
+            self.width = frame.width();
+            self.cells = vec![None; frame.width() * rows];
+            queue!(stdout, ResetColor, terminal::Clear(terminal::ClearType::All))?;
+            self.queue_status(&mut stdout)?;
+        }
+
+        // the cursor and colours are only changed when they have to be, runs of changed cells
+        // are written in one go
+        let mut cursor = None;
+        let mut colours = None;
+        for row in 0..rows {
+            for x in 0..frame.width() {
+                let cell = (frame.colour(x, row * 2, &self.palette), frame.colour(x, row * 2 + 1, &self.palette));
+                let index = row * self.width + x;
+                if self.cells[index] == Some(cell) {
+                    continue;
+                }
+                self.cells[index] = Some(cell);
+
+                if cursor != Some((x, row)) {
+                    queue!(stdout, cursor::MoveTo(x as u16, row as u16))?;
+                }
+                if colours != Some(cell) {
+                    let ((top_r, top_g, top_b), (bottom_r, bottom_g, bottom_b)) = cell;
+                    queue!(stdout, SetForegroundColor(Color::Rgb { r: top_r, g: top_g, b: top_b }),
+                           SetBackgroundColor(Color::Rgb { r: bottom_r, g: bottom_g, b: bottom_b }))?;
+                    colours = Some(cell);
+                }
+                queue!(stdout, Print(HALF_BLOCK))?;
+                cursor = Some((x + 1, row));
+            }
+        }
+        queue!(stdout, ResetColor)?;
+        stdout.flush()
+    }
+
+    // write the status on the line below the display, cut short to fit on one line
+    fn queue_status(&self, stdout: &mut impl Write) -> io::Result<()>{
+        let row = (self.cells.len() / self.width.max(1)) as u16;
+        let status: String = self.status.chars().take(self.size.0 as usize).collect();
+        queue!(stdout, ResetColor, cursor::MoveTo(0, row), terminal::Clear(terminal::ClearType::CurrentLine),
+               Print(status))?;
+        Ok(())
+    }
+
+    fn show_status(&mut self, text: &str){
+        self.status = text.to_string();
+        let mut stdout = io::stdout().lock();
+        let _ = self.queue_status(&mut stdout).and_then(|()| stdout.flush());
+    }
+}
+
+impl VideoSink for TtyScreenDriver{
+    fn draw(&mut self, frame: &Framebuffer){
+        // there is nowhere left to report a broken terminal, the next frame will try again
+        if self.draw_cells(frame).is_err() {
+            self.cells.clear();
+        }
+    }
+
+    fn palette(&self) -> Palette{
+        self.palette
+    }
+
+    fn set_palette(&mut self, palette: Palette){
+        self.palette = palette;
+        // every cell has to be repainted in the new colours
+        self.cells.clear();
+    }
+
+    fn message(&mut self, text: &str){
+        self.show_status(text);
+    }
+
+    fn error(&mut self, text: &str){
+        self.show_status(text);
+        self.errors.push(text.to_string());
+    }
+}
+
+impl Drop for TtyScreenDriver{
+    fn drop(&mut self){
+        // the flags belong to the alternate screen on some terminals, so they are popped before leaving it
+        if self.releases {
+            let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(io::stdout(), ResetColor, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+        for error in self.errors.iter() {
+            eprintln!("{}", error);
+        }
+    }
+}
+
+// reads the keypad from raw mode key events using the same 1234/QWER/ASDF/ZXCV layout as the SDL
+// frontend. Escape or Ctrl+C quit
+pub struct TtyInputDriver{
+    // the number of frames each keypad key and the rewind key are still held for
+    keypad: [u32; 16],
+    rewind: u32,
+    // whether the terminal reports key releases, in which case keys are held until released
+    releases: bool,
+}
+
+impl TtyInputDriver{
+    // releases is whether the terminal reports key releases, see TtyScreenDriver::reports_releases
+    pub fn new(releases: bool) -> TtyInputDriver{
+        TtyInputDriver{
+            keypad: [0; 16],
+            rewind: 0,
+            releases,
+        }
+    }
+
+    // returns true if the key asks to quit
+    fn key(&mut self, key: KeyEvent, hotkeys: &mut Vec<Hotkey>) -> bool{
+        let hold = match key.kind {
+            KeyEventKind::Release => 0,
+            _ if self.releases => u32::MAX,
+            _ => HOLD_FRAMES,
+        };
+        let pressed = key.kind == KeyEventKind::Press;
+        match key.code {
+            KeyCode::Esc => return pressed,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return pressed,
+            // F1-F10 save to slots 1-10 and Shift+F1-F10 load from them
+            KeyCode::F(slot @ 1..=10) if pressed => {
+                let slot = slot as usize;
+                let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+                hotkeys.push(if shift { Hotkey::LoadState(slot) } else { Hotkey::SaveState(slot) });
+            }
+            KeyCode::F(11) if pressed => hotkeys.push(Hotkey::Record),
+            KeyCode::F(12) if pressed => hotkeys.push(Hotkey::Screenshot),
+            KeyCode::Backspace => self.rewind = hold,
+            KeyCode::Char('t') if pressed => hotkeys.push(Hotkey::NextTheme),
+            KeyCode::Char('p') if pressed => hotkeys.push(Hotkey::Phosphor),
+            KeyCode::Char(c) => {
+                if let Some(index) = keypad_index(c.to_ascii_lowercase()) {
+                    self.keypad[index] = hold;
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+}
+
+impl InputSource for TtyInputDriver{
+    fn poll(&mut self) -> InputState{
+        // keys that haven't been reported for a while are let go
+        for held in self.keypad.iter_mut().chain(Some(&mut self.rewind)) {
+            *held = held.saturating_sub(1);
+        }
+
+        let mut hotkeys = Vec::new();
+        let mut quit = false;
+        let mut redraw = false;
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            match event::read() {
+                Ok(Event::Key(key)) => quit |= self.key(key, &mut hotkeys),
+                Ok(Event::Resize(..)) => redraw = true,
+                Ok(_) => {}
+                // the terminal has gone away
+                Err(_) => quit = true,
+            }
+            if quit {
+                break;
+            }
+        }
+
+        let mut keypad = [false; 16];
+        for (key, &held) in keypad.iter_mut().zip(self.keypad.iter()) {
+            *key = held > 0;
+        }
+        InputState { keypad, hotkeys, rewind: self.rewind > 0, quit, redraw }
+    }
+}
+
+// the keypad key a character on the keyboard is mapped to
+fn keypad_index(c: char) -> Option<usize>{
+    match c {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xC),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xD),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xE),
+        'z' => Some(0xA),
+        'x' => Some(0x0),
+        'c' => Some(0xB),
+        'v' => Some(0xF),
+        _ => None,
+    }
+}
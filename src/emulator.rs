@@ -0,0 +1,335 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::cpu::{CpuError, CPU};
+use crate::debugger::Debugger;
+use crate::display::{Framebuffer, Palette};
+use crate::frontend::{AudioSink, Hotkey, InputSource, Tone, VideoSink};
+use crate::phosphor::{Phosphor, PhosphorFilter};
+use crate::recorder::Recorder;
+use crate::rewind::Rewinder;
+use crate::scheduler::FrameScheduler;
+use crate::theme::Theme;
+use crate::screenshot::{capture_path, save_screenshot};
+
+// what to do when the CPU reports an error in the running program
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorPolicy {
+    Halt,
+    Continue,
+    Debug,
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ErrorPolicy, String> {
+        match name {
+            "halt" => Ok(ErrorPolicy::Halt),
+            "continue" => Ok(ErrorPolicy::Continue),
+            "debug" => Ok(ErrorPolicy::Debug),
+            _ => Err(format!("unknown error policy \"{}\", expected halt, continue or debug", name)),
+        }
+    }
+}
+
+// how the emulator loop runs the program
+#[derive(Clone, Debug)]
+pub struct EmulatorConfig {
+    pub instructions_per_frame: usize,
+    pub error_policy: ErrorPolicy,
+    // how much gameplay can be rewound
+    pub rewind_seconds: usize,
+    // start paused in the debugger
+    pub debug: bool,
+    // save states are kept in <state_path>.state1 to <state_path>.state10, next to the ROM
+    pub state_path: String,
+    // the hash of the ROM stored in save states so they can't be loaded into another program
+    pub rom_hash: u64,
+    // where screenshots are saved and how many times bigger than the CHIP8 display they are
+    pub screenshot_dir: PathBuf,
+    pub screenshot_scale: usize,
+    // how many times bigger than the high resolution display recordings are. recordings started
+    // with the hotkey are saved in the screenshot directory
+    pub record_scale: usize,
+    // the themes the theme hotkey cycles through and the one to start in, the video backend's own
+    // colours are left alone if there are none
+    pub themes: Vec<Theme>,
+    pub theme: usize,
+    // the phosphor filter the phosphor hotkey turns on and whether to start with it on
+    pub phosphor: Phosphor,
+    pub phosphor_enabled: bool,
+}
+
+// the main loop, running the CPU a 60Hz frame at a time and passing its output to the backends
+pub struct Emulator<V: VideoSink, A: AudioSink, I: InputSource> {
+    cpu: CPU,
+    video: V,
+    audio: A,
+    input: I,
+    config: EmulatorConfig,
+    rewinder: Rewinder,
+    // the debugger starts paused when asked for, otherwise it only wakes up on an error
+    debugger: Option<Debugger>,
+    recorder: Option<Recorder>,
+    // the phosphor filter while it is on, and the frame last shown after it was filtered
+    filter: Option<PhosphorFilter>,
+    shown: Framebuffer,
+    // the colours of the current theme, kept here rather than asked of the video backend as
+    // backends that show nothing don't remember them, but recordings and screenshots need them
+    palette: Palette,
+    // whether the last frame was shown in time with the display's refresh
+    vsynced: bool,
+}
+
+impl<V: VideoSink, A: AudioSink, I: InputSource> Emulator<V, A, I> {
+    pub fn new(cpu: CPU, mut video: V, audio: A, input: I, config: EmulatorConfig) -> Emulator<V, A, I> {
+        let palette = match config.themes.get(config.theme) {
+            Some(theme) => theme.palette,
+            None => video.palette(),
+        };
+        video.set_palette(palette);
+        let debugger = if config.debug || config.error_policy == ErrorPolicy::Debug {
+            Some(Debugger::new(config.debug))
+        } else {
+            None
+        };
+        let filter = if config.phosphor_enabled {
+            Some(PhosphorFilter::new(config.phosphor))
+        } else {
+            None
+        };
+        Emulator {
+            shown: Framebuffer::new(cpu.display()),
+            cpu,
+            video,
+            audio,
+            input,
+            rewinder: Rewinder::with_seconds(config.rewind_seconds),
+            config,
+            debugger,
+            recorder: None,
+            filter,
+            palette,
+            vsynced: false,
+        }
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn video(&self) -> &V {
+        &self.video
+    }
+
+    pub fn video_mut(&mut self) -> &mut V {
+        &mut self.video
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    // run in real time until the player quits or the program exits. a CPU error only ends the
+    // loop under the halt policy
+    pub fn run(&mut self) -> Result<(), CpuError> {
+        // the timers and display run at 60Hz regardless of how fast the CPU runs
+        let mut scheduler = FrameScheduler::new(60);
+        let result = self.run_realtime(&mut scheduler);
+        self.stop_recording();
+        result
+    }
+
+    fn run_realtime(&mut self, scheduler: &mut FrameScheduler) -> Result<(), CpuError> {
+        while self.run_frame()? {
+            // wait for the rest of the frame so we don't run too quickly
+            scheduler.wait(self.vsynced);
+        }
+        Ok(())
+    }
+
+    // record every frame from now on to a .gif or .y4m file, with the sound in a .wav file next to it
+    pub fn start_recording(&mut self, path: &Path) -> io::Result<()> {
+        self.stop_recording();
+        self.recorder = Some(Recorder::create(path, self.config.record_scale)?);
+        self.video.message(&format!("Recording to {}", path.display()));
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(mut recorder) = self.recorder.take() {
+            match recorder.finish() {
+                Ok(()) => self.video.message("Stopped recording"),
+                Err(error) => self.video.error(&format!("Failed to save recording: {}", error)),
+            }
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    // run the display through the phosphor filter if it is on and draw it if it has changed. this
+    // has to happen every 60Hz frame for the filter to fade at the right speed
+    fn update_display(&mut self, updated: bool) {
+        let frame = Framebuffer::new(self.cpu.display());
+        let frame = match self.filter.as_mut() {
+            Some(filter) => filter.apply(&frame, &self.palette),
+            None => frame,
+        };
+        if updated || frame != self.shown {
+            self.video.draw(&frame);
+        }
+        self.shown = frame;
+    }
+
+    // show the display as it is now, dropping whatever the filter remembers from before. used when
+    // the display jumps to another state or the way it is shown changes
+    fn redraw(&mut self) {
+        if let Some(filter) = self.filter.as_mut() {
+            filter.reset();
+        }
+        self.update_display(true);
+    }
+
+    // add the frame last shown and the sound to the recording, giving up on it if it can't be written
+    fn record(&mut self, tone: Option<Tone>) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(error) = recorder.frame(&self.shown, &self.palette, tone) {
+                self.video.error(&format!("Failed to record: {}", error));
+                self.recorder = None;
+            }
+        }
+    }
+
+    // poll the input and run a single frame as fast as possible, returning false once the loop
+    // should stop
+    pub fn run_frame(&mut self) -> Result<bool, CpuError> {
+        let result = self.step_frame();
+        // however many times the display was drawn this frame it is only shown once
+        self.vsynced = self.video.present();
+        result
+    }
+
+    fn step_frame(&mut self) -> Result<bool, CpuError> {
+        let input = self.input.poll();
+        if input.quit {
+            return Ok(false);
+        }
+        for hotkey in input.hotkeys {
+            self.hotkey(hotkey);
+        }
+        if input.redraw {
+            self.video.draw(&self.shown);
+        }
+
+        // while rewinding step back a frame at a time instead of running the CPU
+        if input.rewind {
+            if self.rewinder.step_back(&mut self.cpu) {
+                self.redraw();
+            }
+            self.audio.set_tone(None);
+            self.record(None);
+            return Ok(true);
+        }
+
+        // run a frame worth of CPU cycles and update the backends based on the CPU
+        let instructions = self.config.instructions_per_frame;
+        let result = match self.debugger.as_mut() {
+            Some(debugger) => self.cpu.run_frame_with(input.keypad, instructions,
+                                                      |cpu| debugger.before_instruction(cpu)),
+            None => self.cpu.run_frame(input.keypad, instructions),
+        };
+        let output = match result {
+            Ok(output) => output,
+            Err(error) => {
+                match (self.config.error_policy, self.debugger.as_mut()) {
+                    (ErrorPolicy::Debug, Some(debugger)) => debugger.report_error(error),
+                    (ErrorPolicy::Halt, _) => return Err(error),
+                    _ => {
+                        self.video.error(&error.to_string());
+                        self.cpu.skip_instruction();
+                    }
+                }
+                return Ok(true);
+            }
+        };
+        if output.exited || self.debugger.as_ref().is_some_and(Debugger::quit_requested) {
+            return Ok(false);
+        }
+        let tone = Tone { pattern: output.audio_pattern, pitch: output.pitch };
+        let tone = if output.play_sound { Some(tone) } else { None };
+        let updated = output.display_updated;
+        self.update_display(updated);
+        self.audio.set_tone(tone);
+        self.record(tone);
+        self.rewinder.push(&self.cpu);
+        Ok(true)
+    }
+
+    fn hotkey(&mut self, hotkey: Hotkey) {
+        match hotkey {
+            Hotkey::SaveState(slot) => {
+                let path = format!("{}.state{}", self.config.state_path, slot);
+                match fs::write(&path, self.cpu.save_state(self.config.rom_hash)) {
+                    Ok(()) => self.video.message(&format!("Saved state to {}", path)),
+                    Err(error) => self.video.error(&format!("Failed to save state to {}: {}", path, error)),
+                }
+            }
+            Hotkey::LoadState(slot) => {
+                let path = format!("{}.state{}", self.config.state_path, slot);
+                let rom_hash = self.config.rom_hash;
+                let cpu = &mut self.cpu;
+                let result = fs::read(&path).map_err(|error| error.to_string())
+                    .and_then(|data| cpu.load_state(&data, rom_hash).map_err(|error| error.to_string()));
+                match result {
+                    Ok(()) => {
+                        self.video.message(&format!("Loaded state from {}", path));
+                        self.redraw();
+                    }
+                    Err(error) => self.video.error(&format!("Failed to load state from {}: {}", path, error)),
+                }
+            }
+            Hotkey::Screenshot => {
+                let result = save_screenshot(&self.shown, &self.palette, self.config.screenshot_scale,
+                                             &self.config.screenshot_dir, &self.config.state_path);
+                match result {
+                    Ok(path) => self.video.message(&format!("Saved screenshot to {}", path.display())),
+                    Err(error) => self.video.error(&format!("Failed to save screenshot: {}", error)),
+                }
+            }
+            Hotkey::NextTheme if !self.config.themes.is_empty() => {
+                self.config.theme = (self.config.theme + 1) % self.config.themes.len();
+                let theme = &self.config.themes[self.config.theme];
+                self.video.message(&format!("Theme {}", theme.name));
+                self.palette = theme.palette;
+                self.video.set_palette(theme.palette);
+                self.redraw();
+            }
+            Hotkey::NextTheme => {}
+            Hotkey::Phosphor => {
+                if self.filter.take().is_some() {
+                    self.video.message("Phosphor off");
+                } else {
+                    self.filter = Some(PhosphorFilter::new(self.config.phosphor));
+                    self.video.message(&format!("Phosphor {}", self.config.phosphor));
+                }
+                self.redraw();
+            }
+            Hotkey::Fullscreen => {
+                self.video.toggle_fullscreen();
+                self.video.draw(&self.shown);
+            }
+            Hotkey::Record if self.is_recording() => self.stop_recording(),
+            Hotkey::Record => {
+                let result = capture_path(&self.config.screenshot_dir, &self.config.state_path, "gif")
+                    .and_then(|path| self.start_recording(&path));
+                if let Err(error) = result {
+                    self.video.error(&format!("Failed to start recording: {}", error));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::display::{Framebuffer, Palette, DEFAULT_PALETTE};
+
+// the traits the emulator loop talks to its backends through, so the same loop can drive an SDL
+// window, a terminal or nothing at all
+
+// shows frames to the player
+pub trait VideoSink {
+    // called whenever the display changes, and with the restored display after a load or rewind
+    fn draw(&mut self, frame: &Framebuffer);
+
+    // called once at the end of every frame, backends that only keep the frames they are given
+    // in draw show the last one here. returns true if showing it waited for the display to refresh
+    fn present(&mut self) -> bool {
+        false
+    }
+
+    // the colours frames are currently shown in, used for screenshots
+    fn palette(&self) -> Palette {
+        DEFAULT_PALETTE
+    }
+
+    // show frames in other colours from now on, the emulator redraws the display afterwards
+    fn set_palette(&mut self, _palette: Palette) {}
+
+    // switch between a window and fullscreen, the emulator redraws the display afterwards
+    fn toggle_fullscreen(&mut self) {}
+
+    // tell the player about something they did, like saving a state. frontends that take over the
+    // terminal have to show these themselves
+    fn message(&mut self, text: &str) {
+        println!("{}", text);
+    }
+
+    // tell the player something went wrong
+    fn error(&mut self, text: &str) {
+        eprintln!("{}", text);
+    }
+}
+
+// a video backend for runs nobody is watching
+pub struct Hidden;
+
+impl VideoSink for Hidden {
+    fn draw(&mut self, _frame: &Framebuffer) {}
+}
+
+// the sound the CPU wants played, the default beep or an XO-CHIP audio pattern at the given pitch
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tone {
+    pub pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+}
+
+// plays the CHIP8 beeper
+pub trait AudioSink {
+    // called every frame, None while the sound timer is zero
+    fn set_tone(&mut self, tone: Option<Tone>);
+}
+
+// an audio backend for frontends that can't make any sound
+pub struct Silent;
+
+impl AudioSink for Silent {
+    fn set_tone(&mut self, _tone: Option<Tone>) {}
+}
+
+// emulator actions bound to keys outside of the CHIP8 keypad
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hotkey {
+    SaveState(usize),
+    LoadState(usize),
+    Screenshot,
+    // start or stop recording
+    Record,
+    // switch to the next colour theme
+    NextTheme,
+    // turn the phosphor filter on or off
+    Phosphor,
+    // switch between a window and fullscreen
+    Fullscreen,
+}
+
+// the state of the CHIP8 keypad along with any hotkeys pressed since the last poll, whether the
+// rewind key is being held, whether the player asked to quit and whether the display has to be
+// drawn again, like after the window was resized
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputState {
+    pub keypad: [bool; 16],
+    pub hotkeys: Vec<Hotkey>,
+    pub rewind: bool,
+    pub quit: bool,
+    pub redraw: bool,
+}
+
+// reads the player's input once a frame
+pub trait InputSource {
+    fn poll(&mut self) -> InputState;
+}
@@ -0,0 +1,38 @@
+use crate::display::{Framebuffer, Palette};
+
+// encode a frame as an RGB PNG coloured with the palette, every CHIP8 pixel becomes a scale x scale
+// square of image pixels
+pub fn encode_png(frame: &Framebuffer, palette: &Palette, scale: usize) -> Vec<u8> {
+    let (width, height) = (frame.width() * scale, frame.height() * scale);
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..frame.width() {
+            let (r, g, b) = frame.colour(x, y / scale, palette);
+            for _ in 0..scale {
+                rgb.extend_from_slice(&[r, g, b]);
+            }
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    // writing to memory can't fail and the header always matches the data
+    let mut writer = encoder.write_header().expect("invalid PNG header");
+    writer.write_image_data(&rgb).expect("PNG data doesn't match its header");
+    writer.finish().expect("could not finish PNG");
+    png
+}
+
+// encode a frame as a plain (text) PBM, pixels set in any plane are black. being text these are
+// easy to diff against the expected output of a test
+pub fn encode_pbm(frame: &Framebuffer) -> Vec<u8> {
+    let mut pbm = format!("P1\n{} {}\n", frame.width(), frame.height());
+    for y in 0..frame.height() {
+        let row: Vec<&str> = frame.row(y).iter().map(|&pixel| if pixel != 0 { "1" } else { "0" }).collect();
+        pbm += &row.join(" ");
+        pbm.push('\n');
+    }
+    pbm.into_bytes()
+}
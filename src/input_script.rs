@@ -0,0 +1,71 @@
+use crate::frontend::{InputSource, InputState};
+
+// keypad input read from a script instead of a player, used to drive headless runs. every line of a
+// script gives the frame to change the keypad on and the keys held from then on as hex digits, or -
+// to let go of every key. # starts a comment:
+//
+//     # press 5 for a tenth of a second after 2 seconds
+//     120 5
+//     126 -
+pub struct InputScript {
+    // keypad changes sorted by frame
+    changes: Vec<(usize, [bool; 16])>,
+    frame: usize,
+    keypad: [bool; 16],
+}
+
+impl InputScript {
+    // a script that never presses anything
+    pub fn empty() -> InputScript {
+        InputScript {
+            changes: Vec::new(),
+            frame: 0,
+            keypad: [false; 16],
+        }
+    }
+
+    pub fn parse(script: &str) -> Result<InputScript, String> {
+        let mut changes = Vec::new();
+        for (number, line) in script.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let frame = fields.next().and_then(|frame| frame.parse::<usize>().ok())
+                .ok_or_else(|| format!("line {}: expected a frame number", number + 1))?;
+            let keys = fields.next().unwrap_or("-");
+            if fields.next().is_some() {
+                return Err(format!("line {}: expected a frame number and the keys held", number + 1));
+            }
+
+            let mut keypad = [false; 16];
+            if keys != "-" {
+                for key in keys.chars() {
+                    match key.to_digit(16) {
+                        Some(key) => keypad[key as usize] = true,
+                        None => return Err(format!("line {}: \"{}\" is not a key, expected 0-F", number + 1, key)),
+                    }
+                }
+            }
+            changes.push((frame, keypad));
+        }
+        // later lines win over earlier ones for the same frame
+        changes.sort_by_key(|&(frame, _)| frame);
+
+        let mut script = InputScript::empty();
+        script.changes = changes;
+        Ok(script)
+    }
+}
+
+impl InputSource for InputScript {
+    fn poll(&mut self) -> InputState {
+        let frame = self.frame;
+        if let Some(&(_, keypad)) = self.changes.iter().rev().find(|&&(at, _)| at == frame) {
+            self.keypad = keypad;
+        }
+        self.frame += 1;
+        InputState { keypad: self.keypad, ..InputState::default() }
+    }
+}
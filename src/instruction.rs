@@ -0,0 +1,225 @@
+// every instruction understood by the CPU, decoded from its opcode. register operands are the
+// register numbers, the names follow the CPU specification at
+// http://www.cs.columbia.edu/~sedwards/classes/2016/4840-spring/designs/Chip8.pdf
+// with the SUPER-CHIP and XO-CHIP extensions added
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    ScrollDown(u8),
+    ScrollUp(u8),
+    ClearScreen,
+    Return,
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    Lores,
+    Hires,
+    Jump(u16),
+    Call(u16),
+    SkipIfEqual(u8, u8),
+    SkipIfNotEqual(u8, u8),
+    SkipIfRegistersEqual(u8, u8),
+    SaveRange(u8, u8),
+    LoadRange(u8, u8),
+    Load(u8, u8),
+    Add(u8, u8),
+    LoadRegister(u8, u8),
+    Or(u8, u8),
+    And(u8, u8),
+    Xor(u8, u8),
+    AddRegisters(u8, u8),
+    Subtract(u8, u8),
+    ShiftRight(u8, u8),
+    SubtractInverted(u8, u8),
+    ShiftLeft(u8, u8),
+    SkipIfRegistersNotEqual(u8, u8),
+    LoadI(u16),
+    JumpWithOffset(u16),
+    Random(u8, u8),
+    Draw(u8, u8, u8),
+    SkipIfKeyPressed(u8),
+    SkipIfKeyNotPressed(u8),
+    // F000 NNNN, the address is the word following the opcode
+    LoadILong,
+    SelectPlanes(u8),
+    LoadAudioPattern,
+    LoadDelayTimer(u8),
+    WaitForKey(u8),
+    SetDelayTimer(u8),
+    SetSoundTimer(u8),
+    AddToI(u8),
+    LoadFont(u8),
+    LoadBigFont(u8),
+    StoreDecimal(u8),
+    SetPitch(u8),
+    StoreRegisters(u8),
+    LoadRegisters(u8),
+    SaveFlags(u8),
+    LoadFlags(u8),
+}
+
+impl Instruction {
+    // decode an opcode, returning None for opcodes that aren't instructions
+    pub fn decode(opcode: u16) -> Option<Instruction> {
+        // split the opcode into each individual nibble
+        let bits = (
+            ((opcode & 0xF000) >> 12) as u8,
+            ((opcode & 0x0F00) >> 8) as u8,
+            ((opcode & 0x00F0) >> 4) as u8,
+            (opcode & 0x000F) as u8,
+        );
+        let nnn = opcode & 0x0FFF;
+        let kk = (opcode & 0x00FF) as u8;
+        let (x, y, n) = (bits.1, bits.2, bits.3);
+
+        let instruction = match bits {
+            (0x0, 0x0, 0xC, _) => Instruction::ScrollDown(n),
+            (0x0, 0x0, 0xD, _) => Instruction::ScrollUp(n),
+            (0x0, 0x0, 0xE, 0x0) => Instruction::ClearScreen,
+            (0x0, 0x0, 0xE, 0xE) => Instruction::Return,
+            (0x0, 0x0, 0xF, 0xB) => Instruction::ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => Instruction::ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Instruction::Exit,
+            (0x0, 0x0, 0xF, 0xE) => Instruction::Lores,
+            (0x0, 0x0, 0xF, 0xF) => Instruction::Hires,
+            (0x1, _, _, _) => Instruction::Jump(nnn),
+            (0x2, _, _, _) => Instruction::Call(nnn),
+            (0x3, _, _, _) => Instruction::SkipIfEqual(x, kk),
+            (0x4, _, _, _) => Instruction::SkipIfNotEqual(x, kk),
+            (0x5, _, _, 0x0) => Instruction::SkipIfRegistersEqual(x, y),
+            (0x5, _, _, 0x2) => Instruction::SaveRange(x, y),
+            (0x5, _, _, 0x3) => Instruction::LoadRange(x, y),
+            (0x6, _, _, _) => Instruction::Load(x, kk),
+            (0x7, _, _, _) => Instruction::Add(x, kk),
+            (0x8, _, _, 0x0) => Instruction::LoadRegister(x, y),
+            (0x8, _, _, 0x1) => Instruction::Or(x, y),
+            (0x8, _, _, 0x2) => Instruction::And(x, y),
+            (0x8, _, _, 0x3) => Instruction::Xor(x, y),
+            (0x8, _, _, 0x4) => Instruction::AddRegisters(x, y),
+            (0x8, _, _, 0x5) => Instruction::Subtract(x, y),
+            (0x8, _, _, 0x6) => Instruction::ShiftRight(x, y),
+            (0x8, _, _, 0x7) => Instruction::SubtractInverted(x, y),
+            (0x8, _, _, 0xE) => Instruction::ShiftLeft(x, y),
+            (0x9, _, _, 0x0) => Instruction::SkipIfRegistersNotEqual(x, y),
+            (0xA, _, _, _) => Instruction::LoadI(nnn),
+            (0xB, _, _, _) => Instruction::JumpWithOffset(nnn),
+            (0xC, _, _, _) => Instruction::Random(x, kk),
+            (0xD, _, _, _) => Instruction::Draw(x, y, n),
+            (0xE, _, 0x9, 0xE) => Instruction::SkipIfKeyPressed(x),
+            (0xE, _, 0xA, 0x1) => Instruction::SkipIfKeyNotPressed(x),
+            (0xF, 0x0, 0x0, 0x0) => Instruction::LoadILong,
+            (0xF, _, 0x0, 0x1) => Instruction::SelectPlanes(x),
+            (0xF, 0x0, 0x0, 0x2) => Instruction::LoadAudioPattern,
+            (0xF, _, 0x0, 0x7) => Instruction::LoadDelayTimer(x),
+            (0xF, _, 0x0, 0xA) => Instruction::WaitForKey(x),
+            (0xF, _, 0x1, 0x5) => Instruction::SetDelayTimer(x),
+            (0xF, _, 0x1, 0x8) => Instruction::SetSoundTimer(x),
+            (0xF, _, 0x1, 0xE) => Instruction::AddToI(x),
+            (0xF, _, 0x2, 0x9) => Instruction::LoadFont(x),
+            (0xF, _, 0x3, 0x0) => Instruction::LoadBigFont(x),
+            (0xF, _, 0x3, 0x3) => Instruction::StoreDecimal(x),
+            (0xF, _, 0x3, 0xA) => Instruction::SetPitch(x),
+            (0xF, _, 0x5, 0x5) => Instruction::StoreRegisters(x),
+            (0xF, _, 0x6, 0x5) => Instruction::LoadRegisters(x),
+            (0xF, _, 0x7, 0x5) => Instruction::SaveFlags(x),
+            (0xF, _, 0x8, 0x5) => Instruction::LoadFlags(x),
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    // encode the instruction back into its opcode, the inverse of decode
+    pub fn encode(self) -> u16 {
+        // build an opcode out of nibbles, an address or a byte
+        fn xyn(op: u16, x: u8, y: u8, n: u8) -> u16 {
+            op << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | (n as u16 & 0xF)
+        }
+        fn nnn(op: u16, nnn: u16) -> u16 {
+            op << 12 | (nnn & 0x0FFF)
+        }
+        fn xkk(op: u16, x: u8, kk: u8) -> u16 {
+            op << 12 | (x as u16 & 0xF) << 8 | kk as u16
+        }
+
+        match self {
+            Instruction::ScrollDown(n) => xyn(0x0, 0x0, 0xC, n),
+            Instruction::ScrollUp(n) => xyn(0x0, 0x0, 0xD, n),
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::Lores => 0x00FE,
+            Instruction::Hires => 0x00FF,
+            Instruction::Jump(addr) => nnn(0x1, addr),
+            Instruction::Call(addr) => nnn(0x2, addr),
+            Instruction::SkipIfEqual(x, kk) => xkk(0x3, x, kk),
+            Instruction::SkipIfNotEqual(x, kk) => xkk(0x4, x, kk),
+            Instruction::SkipIfRegistersEqual(x, y) => xyn(0x5, x, y, 0x0),
+            Instruction::SaveRange(x, y) => xyn(0x5, x, y, 0x2),
+            Instruction::LoadRange(x, y) => xyn(0x5, x, y, 0x3),
+            Instruction::Load(x, kk) => xkk(0x6, x, kk),
+            Instruction::Add(x, kk) => xkk(0x7, x, kk),
+            Instruction::LoadRegister(x, y) => xyn(0x8, x, y, 0x0),
+            Instruction::Or(x, y) => xyn(0x8, x, y, 0x1),
+            Instruction::And(x, y) => xyn(0x8, x, y, 0x2),
+            Instruction::Xor(x, y) => xyn(0x8, x, y, 0x3),
+            Instruction::AddRegisters(x, y) => xyn(0x8, x, y, 0x4),
+            Instruction::Subtract(x, y) => xyn(0x8, x, y, 0x5),
+            Instruction::ShiftRight(x, y) => xyn(0x8, x, y, 0x6),
+            Instruction::SubtractInverted(x, y) => xyn(0x8, x, y, 0x7),
+            Instruction::ShiftLeft(x, y) => xyn(0x8, x, y, 0xE),
+            Instruction::SkipIfRegistersNotEqual(x, y) => xyn(0x9, x, y, 0x0),
+            Instruction::LoadI(addr) => nnn(0xA, addr),
+            Instruction::JumpWithOffset(addr) => nnn(0xB, addr),
+            Instruction::Random(x, kk) => xkk(0xC, x, kk),
+            Instruction::Draw(x, y, n) => xyn(0xD, x, y, n),
+            Instruction::SkipIfKeyPressed(x) => xkk(0xE, x, 0x9E),
+            Instruction::SkipIfKeyNotPressed(x) => xkk(0xE, x, 0xA1),
+            Instruction::LoadILong => 0xF000,
+            Instruction::SelectPlanes(x) => xkk(0xF, x, 0x01),
+            Instruction::LoadAudioPattern => 0xF002,
+            Instruction::LoadDelayTimer(x) => xkk(0xF, x, 0x07),
+            Instruction::WaitForKey(x) => xkk(0xF, x, 0x0A),
+            Instruction::SetDelayTimer(x) => xkk(0xF, x, 0x15),
+            Instruction::SetSoundTimer(x) => xkk(0xF, x, 0x18),
+            Instruction::AddToI(x) => xkk(0xF, x, 0x1E),
+            Instruction::LoadFont(x) => xkk(0xF, x, 0x29),
+            Instruction::LoadBigFont(x) => xkk(0xF, x, 0x30),
+            Instruction::StoreDecimal(x) => xkk(0xF, x, 0x33),
+            Instruction::SetPitch(x) => xkk(0xF, x, 0x3A),
+            Instruction::StoreRegisters(x) => xkk(0xF, x, 0x55),
+            Instruction::LoadRegisters(x) => xkk(0xF, x, 0x65),
+            Instruction::SaveFlags(x) => xkk(0xF, x, 0x75),
+            Instruction::LoadFlags(x) => xkk(0xF, x, 0x85),
+        }
+    }
+
+    // size in bytes, including the address word of F000 NNNN
+    pub fn size(self) -> usize {
+        match self {
+            Instruction::LoadILong => 4,
+            _ => 2,
+        }
+    }
+
+    // skip instructions jump over the whole of the next instruction
+    pub fn is_skip(self) -> bool {
+        matches!(self, Instruction::SkipIfEqual(..) | Instruction::SkipIfNotEqual(..)
+            | Instruction::SkipIfRegistersEqual(..) | Instruction::SkipIfRegistersNotEqual(..)
+            | Instruction::SkipIfKeyPressed(_) | Instruction::SkipIfKeyNotPressed(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_opcode_encodes_back_to_itself() {
+        for opcode in 0..=u16::MAX {
+            if let Some(instruction) = Instruction::decode(opcode) {
+                assert_eq!(instruction.encode(), opcode, "{:04X} decoded to {:?}", opcode, instruction);
+            }
+        }
+    }
+}
@@ -0,0 +1,26 @@
+// the emulator core, shared by the emulator and the command line tools
+pub mod assembler;
+pub mod beeper;
+pub mod cartridge;
+pub mod config;
+pub mod cpu;
+pub mod debugger;
+pub mod disassembler;
+pub mod display;
+pub mod emulator;
+pub mod frontend;
+pub mod image;
+pub mod input_script;
+pub mod instruction;
+pub mod octo;
+pub mod phosphor;
+pub mod quirks;
+pub mod recorder;
+pub mod rewind;
+pub mod rom;
+pub mod rom_db;
+pub mod save_state;
+pub mod scheduler;
+pub mod screenshot;
+pub mod theme;
+pub mod watchpoint;
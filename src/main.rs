@@ -1,52 +1,511 @@
-use std::{env, thread};
-use std::time::Duration;
-use crate::drivers::rom_driver::RomDriver;
+use std::{env, fs};
+#[cfg(feature = "sdl")]
 use crate::drivers::audio_driver::AudioDriver;
+#[cfg(feature = "sdl")]
 use crate::drivers::screen_driver::ScreenDriver;
+#[cfg(feature = "sdl")]
 use crate::drivers::input_driver::InputDriver;
-use crate::cpu::CPU;
+use crate::drivers::tty_driver::{TtyInputDriver, TtyScreenDriver};
+use chip8_core::cpu::CPU;
+use chip8_core::debugger::register_dump;
+use chip8_core::config::Config;
+use chip8_core::display::Framebuffer;
+use chip8_core::emulator::{Emulator, EmulatorConfig, ErrorPolicy};
+use chip8_core::frontend::{AudioSink, Hidden, InputSource, Silent, VideoSink};
+use chip8_core::image::{encode_pbm, encode_png};
+use chip8_core::input_script::InputScript;
+use chip8_core::phosphor::Phosphor;
+use chip8_core::quirks::{QuirkProfile, DEFAULT_INSTRUCTIONS_PER_FRAME};
+use chip8_core::recorder::MAX_RECORD_SCALE;
+use chip8_core::rom::Rom;
+use chip8_core::rom_db::{RomDatabase, RomInfo};
+use chip8_core::save_state::rom_hash;
+use chip8_core::theme::{builtin_themes, parse_palette, Theme};
+use std::path::PathBuf;
 use std::process::exit;
+use std::str::FromStr;
 
 pub mod drivers;
-pub mod cpu;
 
+// how much gameplay can be rewound by holding backspace
+const DEFAULT_REWIND_SECONDS: usize = 10;
+
+// the window starts 20 times the size of the low resolution display, 1280x640
+const DEFAULT_WINDOW_SCALE: u32 = 20;
+
+// recordings are 4 times the size of the high resolution display, 512x256
+const DEFAULT_RECORD_SCALE: usize = 4;
+
+// where the game is shown and played, an SDL window or the terminal
+#[derive(Clone, Copy, PartialEq)]
+enum Frontend {
+    Sdl,
+    Tty,
+}
+
+impl FromStr for Frontend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Frontend, String> {
+        match name {
+            "sdl" if cfg!(feature = "sdl") => Ok(Frontend::Sdl),
+            "sdl" => Err("this build doesn't include the SDL frontend, use --frontend tty".to_string()),
+            "tty" => Ok(Frontend::Tty),
+            _ => Err(format!("unknown frontend \"{}\", expected sdl or tty", name)),
+        }
+    }
+}
 
 pub fn main(){
-    // set a sleep time between ticks so we don't absolutely blow through our program
-    let sleep_duration = Duration::from_millis(2);
+    // search the provided arguments for the ROM to load, the quirk profile to run it with and the
+    // number of instructions to run every frame
+    let mut rom_file = None;
+    let mut profile = None;
+    let mut instructions_per_frame = None;
+    let mut error_policy = ErrorPolicy::Halt;
+    let mut rewind_seconds = DEFAULT_REWIND_SECONDS;
+    let mut debug = false;
+    let mut rom_db_file = None;
+    let mut window_scale = DEFAULT_WINDOW_SCALE;
+    let mut frontend = if cfg!(feature = "sdl") { Frontend::Sdl } else { Frontend::Tty };
+    let mut headless_frames = None;
+    let mut input_script_file = None;
+    let mut dump_file = None;
+    let mut screenshot_dir = PathBuf::from(".");
+    let mut screenshot_scale = 1;
+    let mut record_file = None;
+    let mut record_scale = DEFAULT_RECORD_SCALE;
+    let mut theme_name = None;
+    let mut phosphor_name = None;
+    let mut config_file = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--quirks" {
+            let name = args.next().unwrap_or_default();
+            profile = match QuirkProfile::from_name(&name) {
+                Some(profile) => Some(profile),
+                None => {
+                    eprintln!("Error, unknown quirk profile \"{}\", expected one of vip, chip48, schip or xochip", name);
+                    exit(1);
+                }
+            };
+        } else if arg == "--ipf" {
+            instructions_per_frame = match args.next().and_then(|count| count.parse::<usize>().ok()) {
+                Some(count) => Some(count),
+                None => {
+                    eprintln!("Error, --ipf expects the number of instructions to run per frame");
+                    exit(1);
+                }
+            };
+        } else if arg == "--rom-db" {
+            rom_db_file = match args.next() {
+                Some(file) => Some(file),
+                None => {
+                    eprintln!("Error, --rom-db expects a ROM database in the chip-8-database programs.json format");
+                    exit(1);
+                }
+            };
+        } else if arg == "--scale" {
+            window_scale = match args.next().and_then(|scale| scale.parse::<u32>().ok()).filter(|&scale| scale > 0) {
+                Some(scale) => scale,
+                None => {
+                    eprintln!("Error, --scale expects how many times bigger than the display the window starts");
+                    exit(1);
+                }
+            };
+        } else if arg == "--frontend" {
+            frontend = match args.next().unwrap_or_default().parse() {
+                Ok(frontend) => frontend,
+                Err(message) => {
+                    eprintln!("Error, {}", message);
+                    exit(1);
+                }
+            };
+        } else if arg == "--headless" {
+            headless_frames = match args.next().and_then(|frames| frames.parse::<usize>().ok()) {
+                Some(frames) => Some(frames),
+                None => {
+                    eprintln!("Error, --headless expects the number of frames to run");
+                    exit(1);
+                }
+            };
+        } else if arg == "--input" {
+            input_script_file = match args.next() {
+                Some(file) => Some(file),
+                None => {
+                    eprintln!("Error, --input expects a script of the keys to press");
+                    exit(1);
+                }
+            };
+        } else if arg == "--dump" {
+            dump_file = match args.next() {
+                Some(file) => Some(file),
+                None => {
+                    eprintln!("Error, --dump expects a .png or .pbm file to write the display to");
+                    exit(1);
+                }
+            };
+        } else if arg == "--screenshot-dir" {
+            screenshot_dir = match args.next() {
+                Some(dir) => PathBuf::from(dir),
+                None => {
+                    eprintln!("Error, --screenshot-dir expects the directory to save screenshots in");
+                    exit(1);
+                }
+            };
+        } else if arg == "--screenshot-scale" {
+            screenshot_scale = match args.next().and_then(|scale| scale.parse::<usize>().ok()).filter(|&scale| scale > 0) {
+                Some(scale) => scale,
+                None => {
+                    eprintln!("Error, --screenshot-scale expects how many times bigger than the display screenshots are");
+                    exit(1);
+                }
+            };
+        } else if arg == "--record" {
+            record_file = match args.next() {
+                Some(file) => Some(PathBuf::from(file)),
+                None => {
+                    eprintln!("Error, --record expects a .gif or .y4m file to record to");
+                    exit(1);
+                }
+            };
+        } else if arg == "--record-scale" {
+            record_scale = match args.next().and_then(|scale| scale.parse::<usize>().ok())
+                .filter(|scale| (1..=MAX_RECORD_SCALE).contains(scale)) {
+                Some(scale) => scale,
+                None => {
+                    eprintln!("Error, --record-scale expects how many times bigger than the display recordings are, from 1 to {}",
+                              MAX_RECORD_SCALE);
+                    exit(1);
+                }
+            };
+        } else if arg == "--theme" {
+            theme_name = match args.next() {
+                Some(theme) => Some(theme),
+                None => {
+                    eprintln!("Error, --theme expects the name of a theme or its hex colours");
+                    exit(1);
+                }
+            };
+        } else if arg == "--phosphor" {
+            phosphor_name = match args.next() {
+                Some(phosphor) => Some(phosphor),
+                None => {
+                    eprintln!("Error, --phosphor expects off, fade[:amount] or blend[:frames]");
+                    exit(1);
+                }
+            };
+        } else if arg == "--config" {
+            config_file = match args.next() {
+                Some(file) => Some(PathBuf::from(file)),
+                None => {
+                    eprintln!("Error, --config expects a settings file");
+                    exit(1);
+                }
+            };
+        } else if arg == "--debug" {
+            debug = true;
+        } else if arg == "--rewind-seconds" {
+            rewind_seconds = match args.next().and_then(|seconds| seconds.parse::<usize>().ok()) {
+                Some(seconds) => seconds,
+                None => {
+                    eprintln!("Error, --rewind-seconds expects the number of seconds of history to keep");
+                    exit(1);
+                }
+            };
+        } else if arg == "--on-error" {
+            error_policy = match args.next().unwrap_or_default().parse() {
+                Ok(policy) => policy,
+                Err(message) => {
+                    eprintln!("Error, {}", message);
+                    exit(1);
+                }
+            };
+        } else {
+            rom_file = Some(arg);
+        }
+    }
+    let rom_file = match rom_file {
+        Some(rom_file) => rom_file,
+        None => {
+            eprintln!("Error, please provide a file to run as an argument");
+            exit(1);
+        }
+    };
+
+    // the debugger prompts on the terminal the TTY frontend draws the game on and reads keys from
+    if frontend == Frontend::Tty && headless_frames.is_none() && (debug || error_policy == ErrorPolicy::Debug) {
+        eprintln!("Error, the debugger can't be used with --frontend tty");
+        exit(1);
+    }
+
+    // the bundled database is extended with the user's programs.json if there is one and then the
+    // one given on the command line, each replacing any entries the ones before it had for a ROM
+    let mut database = RomDatabase::bundled();
+    let user_rom_db_file = settings_dir().map(|dir| dir.join("programs.json")).filter(|file| file.exists());
+    for rom_db_file in user_rom_db_file.into_iter().chain(rom_db_file.map(PathBuf::from)) {
+        let result = fs::read_to_string(&rom_db_file).map_err(|error| error.to_string())
+            .and_then(|json| database.merge(&json));
+        if let Err(message) = result {
+            eprintln!("Error, could not load ROM database {}: {}", rom_db_file.display(), message);
+            exit(1);
+        }
+    }
+    let rom = match Rom::load(&rom_file, &database) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("Error, could not load {}: {}", rom_file, err);
+            exit(1);
+        }
+    };
+
+    // options given on the command line win over those recommended by an Octo cartridge, which
+    // win over what the ROM database knows about the program
+    let cartridge = rom.options.clone().unwrap_or_default();
+    let info = rom.info.clone().unwrap_or_default();
+    let quirks = profile.map(QuirkProfile::quirks)
+        .or(cartridge.quirks)
+        .or_else(|| info.platform.map(QuirkProfile::quirks))
+        .unwrap_or_default();
+    let instructions_per_frame = instructions_per_frame
+        .or_else(|| profile.map(QuirkProfile::instructions_per_frame))
+        .or(cartridge.instructions_per_frame)
+        .or(info.instructions_per_frame)
+        .or_else(|| info.platform.map(QuirkProfile::instructions_per_frame))
+        .unwrap_or(DEFAULT_INSTRUCTIONS_PER_FRAME);
+    if rom.info.is_some() {
+        print_rom_info(&info);
+    }
+
+    // the settings file is optional unless one was asked for
+    let settings = match config_file.clone().or_else(default_config_file) {
+        Some(file) if config_file.is_some() || file.exists() => {
+            match fs::read_to_string(&file).map_err(|error| error.to_string()).and_then(|text| Config::parse(&text)) {
+                Ok(settings) => settings,
+                Err(message) => {
+                    eprintln!("Error, could not load settings {}: {}", file.display(), message);
+                    exit(1);
+                }
+            }
+        }
+        _ => Config::default(),
+    };
+
+    // a theme given on the command line wins over the colours of the ROM, which win over the theme
+    // in the settings file
+    let mut themes = builtin_themes();
+    themes.extend(settings.themes);
+    let find_theme = |name: &str| themes.iter().position(|theme| theme.name == name);
+    let theme = match theme_name.as_deref() {
+        Some(name) => match find_theme(name) {
+            Some(theme) => theme,
+            None => match parse_palette(name) {
+                Some(palette) => add_theme(&mut themes, Theme::new("custom", palette)),
+                None => {
+                    eprintln!("Error, unknown theme \"{}\", expected a theme name or 2 or 4 hex colours", name);
+                    exit(1);
+                }
+            },
+        },
+        None => match (cartridge.palette.or(info.palette), settings.theme) {
+            (Some(palette), _) => add_theme(&mut themes, Theme::new("ROM", palette)),
+            (None, Some(name)) => match find_theme(&name) {
+                Some(theme) => theme,
+                None => {
+                    eprintln!("Error, unknown theme \"{}\" in the settings file", name);
+                    exit(1);
+                }
+            },
+            (None, None) => 0,
+        },
+    };
+
+    // the phosphor filter given on the command line wins over the one in the settings file, the
+    // hotkey turns on the default filter if neither picks one
+    let (phosphor, phosphor_source) = match (phosphor_name, settings.phosphor) {
+        (Some(name), _) => (Some(name), "--phosphor"),
+        (None, name) => (name, "the settings file"),
+    };
+    let phosphor = match phosphor.as_deref().map(parse_phosphor).transpose() {
+        Ok(phosphor) => phosphor.flatten(),
+        Err(message) => {
+            eprintln!("Error, invalid phosphor filter in {}: {}", phosphor_source, message);
+            exit(1);
+        }
+    };
+
+    let mut cpu = CPU::new(quirks);
+    cpu.load(&rom.data);
+    let config = EmulatorConfig {
+        instructions_per_frame,
+        error_policy,
+        rewind_seconds,
+        debug,
+        state_path: rom_file,
+        rom_hash: rom_hash(&rom.data),
+        screenshot_dir,
+        screenshot_scale,
+        record_scale,
+        themes,
+        theme,
+        phosphor: phosphor.unwrap_or_default(),
+        phosphor_enabled: phosphor.is_some(),
+    };
+
+    // headless runs don't touch the display, audio or keyboard so they work anywhere
+    if let Some(frames) = headless_frames {
+        let input = match input_script_file {
+            Some(file) => fs::read_to_string(&file).map_err(|error| error.to_string())
+                .and_then(|script| InputScript::parse(&script))
+                .unwrap_or_else(|message| {
+                    eprintln!("Error, could not load input script {}: {}", file, message);
+                    exit(1);
+                }),
+            None => InputScript::empty(),
+        };
+        let emulator = Emulator::new(cpu, Hidden, Silent, input, config);
+        run_headless(emulator, record_file, frames, dump_file);
+        return;
+    }
+
+    // only the SDL frontend has a window to size
+    #[cfg(not(feature = "sdl"))]
+    let _ = window_scale;
 
-    // make our SDL handle
-    let sdl_context = sdl2::init().unwrap();
+    // initialize the drivers of the chosen frontend and hand them to the emulator
+    match frontend {
+        #[cfg(feature = "sdl")]
+        Frontend::Sdl => {
+            let sdl_context = sdl2::init().unwrap();
+            let audio_driver = AudioDriver::new(&sdl_context);
+            let mut screen_driver = ScreenDriver::new(&sdl_context, window_scale);
+            if !info.title.is_empty() {
+                screen_driver.set_title(&info.title);
+            }
+            let input_driver = InputDriver::new(&sdl_context);
+            run(Emulator::new(cpu, screen_driver, audio_driver, input_driver, config), record_file);
+        }
+        #[cfg(not(feature = "sdl"))]
+        Frontend::Sdl => unreachable!("the SDL frontend can't be selected without the sdl feature"),
+        Frontend::Tty => {
+            let screen_driver = match TtyScreenDriver::new() {
+                Ok(screen_driver) => screen_driver,
+                Err(error) => {
+                    eprintln!("Error, could not set up the terminal: {}", error);
+                    exit(1);
+                }
+            };
+            let input_driver = TtyInputDriver::new(screen_driver.reports_releases());
+            run(Emulator::new(cpu, screen_driver, Silent, input_driver, config), record_file);
+        }
+    }
+}
+
+// run the emulator until the player quits, the drivers are dropped before exiting so they can put
+// the terminal back the way they found it
+fn run<V: VideoSink, A: AudioSink, I: InputSource>(mut emulator: Emulator<V, A, I>, record_file: Option<PathBuf>){
+    if !start_recording(&mut emulator, record_file) {
+        drop(emulator);
+        exit(1);
+    }
+    let result = emulator.run();
+    drop(emulator);
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        exit(1);
+    }
+}
 
-    // search the provided arguments for the ROM to load
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2{
-        eprintln!("Error, please provide a file to run as an argument");
+// run as fast as possible for the given number of frames or until the program exits or gets stuck
+// jumping to itself, then write out the display and print the registers
+fn run_headless(mut emulator: Emulator<Hidden, Silent, InputScript>, record_file: Option<PathBuf>, frames: usize,
+                dump_file: Option<String>){
+    if !start_recording(&mut emulator, record_file) {
         exit(1);
     }
-    let rom_file = &args[1];
-
-    // initialize our drivers and CPU
-    let rom_driver = RomDriver::new(rom_file);
-    let mut audio_driver = AudioDriver::new(&sdl_context);
-    let mut screen_driver = ScreenDriver::new(&sdl_context);
-    let mut input_driver = InputDriver::new(&sdl_context);
-    let mut cpu = CPU::new();
-    cpu.load(&rom_driver.rom);
-
-    // main game loop
-    while let Ok(keypad) = input_driver.poll() {
-        // make a CPU cycle and update the required drivers based on the CPU
-        let output = cpu.tick(keypad);
-        if output.display_updated {
-            screen_driver.draw(output.display);
-        }
-        if output.play_sound {
-            audio_driver.start();
+    let mut result = Ok(());
+    let mut frame = 0;
+    while frame < frames {
+        frame += 1;
+        match emulator.run_frame() {
+            Ok(true) if !emulator.cpu().is_spinning() => {}
+            Ok(_) => break,
+            Err(error) => {
+                result = Err(error);
+                break;
+            }
+        }
+    }
+    println!("Stopped after {} frames", frame);
+    emulator.stop_recording();
+
+    if let Some(dump_file) = dump_file {
+        let frame = Framebuffer::new(emulator.cpu().display());
+        let image = if dump_file.to_lowercase().ends_with(".pbm") {
+            encode_pbm(&frame)
         } else {
-            audio_driver.stop();
+            encode_png(&frame, &emulator.palette(), 1)
+        };
+        if let Err(error) = fs::write(&dump_file, image) {
+            eprintln!("Error, could not write {}: {}", dump_file, error);
+            exit(1);
         }
-        // sleep a little bit between cycles so we don't run too quickly
-        thread::sleep(sleep_duration);
     }
-}
\ No newline at end of file
+    print!("{}", register_dump(emulator.cpu()));
+
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        exit(1);
+    }
+}
+
+// the directory the user's settings file and ROM database are kept in
+fn settings_dir() -> Option<PathBuf>{
+    let config_dir = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("chip8"))
+}
+
+// where the settings file is looked for when --config isn't given
+fn default_config_file() -> Option<PathBuf>{
+    settings_dir().map(|dir| dir.join("config"))
+}
+
+// a phosphor filter as given on the command line or in the settings file, None when it is off
+fn parse_phosphor(text: &str) -> Result<Option<Phosphor>, String>{
+    match text {
+        "off" => Ok(None),
+        _ => text.parse().map(Some),
+    }
+}
+
+// put a theme that isn't one of the named ones at the front of the list the theme hotkey cycles
+// through, returning its index
+fn add_theme(themes: &mut Vec<Theme>, theme: Theme) -> usize{
+    themes.insert(0, theme);
+    0
+}
+
+// start the recording asked for on the command line, returning false if it couldn't be
+fn start_recording<V: VideoSink, A: AudioSink, I: InputSource>(emulator: &mut Emulator<V, A, I>,
+                                                               record_file: Option<PathBuf>) -> bool{
+    match record_file.map(|file| emulator.start_recording(&file).map_err(|error| (file, error))) {
+        Some(Err((file, error))) => {
+            eprintln!("Error, could not record to {}: {}", file.display(), error);
+            false
+        }
+        _ => true,
+    }
+}
+
+// tell the player what they are playing and how to play it
+fn print_rom_info(info: &RomInfo) {
+    if info.authors.is_empty() {
+        println!("{}", info.title);
+    } else {
+        println!("{} by {}", info.title, info.authors.join(", "));
+    }
+    for (action, key) in info.keys.iter() {
+        println!("  {:X}  {}", key, action);
+    }
+}
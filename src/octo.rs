@@ -0,0 +1,833 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use crate::assembler::AsmError;
+use crate::cpu::{CHIP8_RAM, PROGRAM_START};
+use crate::instruction::Instruction;
+
+// a compiler for Octo, the structured assembly language most CHIP-8 homebrew is written in. see
+// https://github.com/JohnEarnest/Octo/blob/gh-pages/docs/Manual.md for the language itself
+
+// stops a macro that expands itself from running forever
+const MAX_MACRO_EXPANSIONS: usize = 10000;
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+// an address used before its label was defined, patched in once every label is known
+struct Fixup {
+    addr: usize,
+    token: Token,
+    long: bool,
+}
+
+struct Loop {
+    start: usize,
+    token: Token,
+    // the jumps out of the loop from while statements
+    breaks: Vec<usize>,
+}
+
+// compile Octo source into a ROM image to be loaded at PROGRAM_START, the name is used in errors
+pub fn compile(source: &str, name: &str) -> Result<Vec<u8>, AsmError> {
+    let mut compiler = Compiler {
+        name,
+        tokens: tokenize(source),
+        last: Token { text: String::new(), line: 1, column: 1 },
+        rom: Vec::new(),
+        here: PROGRAM_START,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        loops: Vec::new(),
+        branches: Vec::new(),
+        expansions: 0,
+    };
+    compiler.program()?;
+    Ok(compiler.rom)
+}
+
+struct Compiler<'a> {
+    name: &'a str,
+    tokens: VecDeque<Token>,
+    // the most recently read token, errors that aren't about a particular token point here
+    last: Token,
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    loops: Vec<Loop>,
+    // the jumps of open if ... begin blocks waiting for their else or end
+    branches: Vec<(usize, Token)>,
+    expansions: usize,
+}
+
+impl<'a> Compiler<'a> {
+    fn program(&mut self) -> Result<(), AsmError> {
+        // execution starts at PROGRAM_START, so jump to main unless it is defined right there
+        let starts_with_main = self.tokens.front().is_some_and(|token| token.text == ":")
+            && self.tokens.get(1).is_some_and(|token| token.text == "main");
+        if !starts_with_main {
+            let token = Token { text: "main".to_string(), line: 1, column: 1 };
+            self.emit_address(Instruction::Jump, &token)?;
+        }
+
+        while let Some(token) = self.tokens.pop_front() {
+            self.last = token.clone();
+            self.statement(token)?;
+        }
+
+        if let Some(open) = self.loops.last() {
+            return Err(self.error_at(&open.token, "loop is missing its again".to_string()));
+        }
+        if let Some((_, token)) = self.branches.last() {
+            return Err(self.error_at(token, "begin is missing its end".to_string()));
+        }
+        if !self.labels.contains_key("main") {
+            return Err(self.error_at(&self.last, "the program has no main label".to_string()));
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let addr = match self.labels.get(&fixup.token.text) {
+                Some(&addr) => addr,
+                None => return Err(self.error_at(&fixup.token, format!("undefined name \"{}\"", fixup.token.text))),
+            };
+            let offset = fixup.addr - PROGRAM_START;
+            if fixup.long {
+                self.rom[offset..offset + 2].copy_from_slice(&(addr as u16).to_be_bytes());
+            } else if addr > 0xFFF {
+                return Err(self.error_at(&fixup.token, format!("\"{}\" is past 0xFFF, use i := long", fixup.token.text)));
+            } else {
+                self.rom[offset] |= (addr >> 8) as u8;
+                self.rom[offset + 1] = addr as u8;
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AsmError> {
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name_token()?;
+                if self.labels.insert(name.text.clone(), self.here).is_some() {
+                    return Err(self.error_at(&name, format!("\"{}\" is already defined", name.text)));
+                }
+            }
+            ":alias" => {
+                let name = self.name_token()?;
+                let register = self.next()?;
+                let register = self.register(&register)?;
+                self.aliases.insert(name.text, register);
+            }
+            ":const" => {
+                let name = self.name_token()?;
+                let value = self.next()?;
+                let value = self.number(&value)?;
+                self.constants.insert(name.text, value);
+            }
+            ":calc" => {
+                let name = self.name_token()?;
+                let value = self.calc()?;
+                self.constants.insert(name.text, value);
+            }
+            ":byte" => {
+                let value = if self.peek_is("{") {
+                    let at = self.last.clone();
+                    let value = self.calc()?;
+                    self.byte_value(value, &at)?
+                } else {
+                    let token = self.next()?;
+                    self.byte(&token)?
+                };
+                self.emit_byte(value)?;
+            }
+            ":org" => {
+                let token = self.next()?;
+                let addr = self.number(&token)?;
+                if addr < PROGRAM_START as f64 || addr >= CHIP8_RAM as f64 {
+                    return Err(self.error_at(&token, format!("{} is outside the program's memory", addr)));
+                }
+                self.here = addr as usize;
+            }
+            ":macro" => self.define_macro()?,
+            ":call" => {
+                let target = self.next()?;
+                self.emit_address(Instruction::Call, &target)?;
+            }
+            // debugging hints for Octo's own emulator
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            "return" | ";" => self.emit(Instruction::Return)?,
+            "clear" => self.emit(Instruction::ClearScreen)?,
+            "hires" => self.emit(Instruction::Hires)?,
+            "lores" => self.emit(Instruction::Lores)?,
+            "exit" => self.emit(Instruction::Exit)?,
+            "scroll-left" => self.emit(Instruction::ScrollLeft)?,
+            "scroll-right" => self.emit(Instruction::ScrollRight)?,
+            "audio" => self.emit(Instruction::LoadAudioPattern)?,
+            "scroll-down" => {
+                let n = self.next_nibble()?;
+                self.emit(Instruction::ScrollDown(n))?;
+            }
+            "scroll-up" => {
+                let n = self.next_nibble()?;
+                self.emit(Instruction::ScrollUp(n))?;
+            }
+            "plane" => {
+                let n = self.next_nibble()?;
+                self.emit(Instruction::SelectPlanes(n))?;
+            }
+            "bcd" => {
+                let x = self.next_register()?;
+                self.emit(Instruction::StoreDecimal(x))?;
+            }
+            "save" | "load" => {
+                let x = self.next_register()?;
+                let instruction = if self.peek_is("-") {
+                    self.next()?;
+                    let y = self.next_register()?;
+                    if token.text == "save" { Instruction::SaveRange(x, y) } else { Instruction::LoadRange(x, y) }
+                } else if token.text == "save" {
+                    Instruction::StoreRegisters(x)
+                } else {
+                    Instruction::LoadRegisters(x)
+                };
+                self.emit(instruction)?;
+            }
+            "saveflags" => {
+                let x = self.next_register()?;
+                self.emit(Instruction::SaveFlags(x))?;
+            }
+            "loadflags" => {
+                let x = self.next_register()?;
+                self.emit(Instruction::LoadFlags(x))?;
+            }
+            "sprite" => {
+                let x = self.next_register()?;
+                let y = self.next_register()?;
+                let n = self.next_nibble()?;
+                self.emit(Instruction::Draw(x, y, n))?;
+            }
+            "jump" => {
+                let target = self.next()?;
+                self.emit_address(Instruction::Jump, &target)?;
+            }
+            "jump0" => {
+                let target = self.next()?;
+                self.emit_address(Instruction::JumpWithOffset, &target)?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.next_register()?;
+                self.emit(match token.text.as_str() {
+                    "delay" => Instruction::SetDelayTimer(x),
+                    "buzzer" => Instruction::SetSoundTimer(x),
+                    _ => Instruction::SetPitch(x),
+                })?;
+            }
+            "i" => self.i_statement()?,
+            "if" => {
+                let (prefix, skip) = self.condition()?;
+                let keyword = self.next()?;
+                match keyword.text.as_str() {
+                    "then" => {
+                        self.emit_all(&prefix)?;
+                        self.emit(skip)?;
+                    }
+                    "begin" => {
+                        self.emit_all(&prefix)?;
+                        self.emit(negate(skip))?;
+                        self.branches.push((self.here, token));
+                        self.emit(Instruction::Jump(0))?;
+                    }
+                    _ => return Err(self.error_at(&keyword, "expected then or begin".to_string())),
+                }
+            }
+            "else" => {
+                let (branch, opened) = match self.branches.pop() {
+                    Some(branch) => branch,
+                    None => return Err(self.error_at(&token, "else without a matching begin".to_string())),
+                };
+                let here = self.here;
+                self.emit(Instruction::Jump(0))?;
+                self.patch_jump(branch, self.here, &token)?;
+                self.branches.push((here, opened));
+            }
+            "end" => match self.branches.pop() {
+                Some((branch, opened)) => self.patch_jump(branch, self.here, &opened)?,
+                None => return Err(self.error_at(&token, "end without a matching begin".to_string())),
+            },
+            "loop" => self.loops.push(Loop { start: self.here, token, breaks: Vec::new() }),
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(self.error_at(&token, "while outside of a loop".to_string()));
+                }
+                let (prefix, skip) = self.condition()?;
+                self.emit_all(&prefix)?;
+                self.emit(negate(skip))?;
+                let here = self.here;
+                if let Some(open) = self.loops.last_mut() {
+                    open.breaks.push(here);
+                }
+                self.emit(Instruction::Jump(0))?;
+            }
+            "again" => {
+                let open = match self.loops.pop() {
+                    Some(open) => open,
+                    None => return Err(self.error_at(&token, "again without a matching loop".to_string())),
+                };
+                let jump = self.jump(open.start, &open.token)?;
+                self.emit(jump)?;
+                for addr in open.breaks {
+                    self.patch_jump(addr, self.here, &token)?;
+                }
+            }
+            text if self.macros.contains_key(text) => self.expand_macro(&token)?,
+            _ if self.is_register(&token) => self.register_statement(&token)?,
+            // numbers and constants on their own are data, any other name calls a subroutine
+            _ if parse_number(&token.text).is_some() || self.constants.contains_key(&token.text) => {
+                let value = self.byte(&token)?;
+                self.emit_byte(value)?;
+            }
+            _ if is_name(&token.text) => self.emit_address(Instruction::Call, &token)?,
+            _ => return Err(self.error_at(&token, format!("unexpected \"{}\"", token.text))),
+        }
+        Ok(())
+    }
+
+    fn i_statement(&mut self) -> Result<(), AsmError> {
+        let op = self.next()?;
+        match op.text.as_str() {
+            ":=" => {
+                let value = self.next()?;
+                match value.text.as_str() {
+                    "hex" => {
+                        let x = self.next_register()?;
+                        self.emit(Instruction::LoadFont(x))
+                    }
+                    "bighex" => {
+                        let x = self.next_register()?;
+                        self.emit(Instruction::LoadBigFont(x))
+                    }
+                    "long" => {
+                        let target = self.next()?;
+                        self.emit(Instruction::LoadILong)?;
+                        match self.address(&target, 0xFFFF)? {
+                            Some(addr) => self.emit_word(addr),
+                            None => {
+                                self.fixups.push(Fixup { addr: self.here, token: target, long: true });
+                                self.emit_word(0)
+                            }
+                        }
+                    }
+                    _ => self.emit_address(Instruction::LoadI, &value),
+                }
+            }
+            "+=" => {
+                let x = self.next_register()?;
+                self.emit(Instruction::AddToI(x))
+            }
+            _ => Err(self.error_at(&op, "expected := or += after i".to_string())),
+        }
+    }
+
+    fn register_statement(&mut self, register: &Token) -> Result<(), AsmError> {
+        let x = self.register(register)?;
+        let op = self.next()?;
+        let rhs = self.next()?;
+        let y = if self.is_register(&rhs) { Some(self.register(&rhs)?) } else { None };
+        let instruction = match (op.text.as_str(), y) {
+            (":=", Some(y)) => Instruction::LoadRegister(x, y),
+            (":=", None) => match rhs.text.as_str() {
+                "random" => {
+                    let mask = self.next()?;
+                    Instruction::Random(x, self.byte(&mask)?)
+                }
+                "key" => Instruction::WaitForKey(x),
+                "delay" => Instruction::LoadDelayTimer(x),
+                _ => Instruction::Load(x, self.byte(&rhs)?),
+            },
+            ("+=", Some(y)) => Instruction::AddRegisters(x, y),
+            ("+=", None) => Instruction::Add(x, self.byte(&rhs)?),
+            ("-=", Some(y)) => Instruction::Subtract(x, y),
+            // there is no subtract immediate, adding the negated value wraps around to the same result
+            ("-=", None) => Instruction::Add(x, self.byte(&rhs)?.wrapping_neg()),
+            ("=-", Some(y)) => Instruction::SubtractInverted(x, y),
+            ("|=", Some(y)) => Instruction::Or(x, y),
+            ("&=", Some(y)) => Instruction::And(x, y),
+            ("^=", Some(y)) => Instruction::Xor(x, y),
+            (">>=", Some(y)) => Instruction::ShiftRight(x, y),
+            ("<<=", Some(y)) => Instruction::ShiftLeft(x, y),
+            ("=-", None) | ("|=", None) | ("&=", None) | ("^=", None) | (">>=", None) | ("<<=", None) => {
+                return Err(self.error_at(&rhs, format!("{} needs a register", op.text)));
+            }
+            _ => return Err(self.error_at(&op, format!("unknown operator \"{}\"", op.text))),
+        };
+        self.emit(instruction)
+    }
+
+    // a condition compiles to the instructions setting it up and a skip taken when it is false. the
+    // ordered comparisons subtract into vf and test the carry, like Octo does
+    fn condition(&mut self) -> Result<(Vec<Instruction>, Instruction), AsmError> {
+        let lhs = self.next()?;
+        let x = self.register(&lhs)?;
+        let op = self.next()?;
+        match op.text.as_str() {
+            "key" => return Ok((Vec::new(), Instruction::SkipIfKeyNotPressed(x))),
+            "-key" => return Ok((Vec::new(), Instruction::SkipIfKeyPressed(x))),
+            _ => {}
+        }
+        let rhs = self.next()?;
+        let y = if self.is_register(&rhs) { Some(self.register(&rhs)?) } else { None };
+        let kk = match y {
+            Some(_) => 0,
+            None => self.byte(&rhs)?,
+        };
+        let condition = match (op.text.as_str(), y) {
+            ("==", Some(y)) => (Vec::new(), Instruction::SkipIfRegistersNotEqual(x, y)),
+            ("==", None) => (Vec::new(), Instruction::SkipIfNotEqual(x, kk)),
+            ("!=", Some(y)) => (Vec::new(), Instruction::SkipIfRegistersEqual(x, y)),
+            ("!=", None) => (Vec::new(), Instruction::SkipIfEqual(x, kk)),
+            // vf = x - y, with vf ending up 1 when x >= y
+            ("<", _) | (">=", _) => {
+                let prefix = match y {
+                    Some(y) => vec![Instruction::LoadRegister(0xF, x), Instruction::Subtract(0xF, y)],
+                    None => vec![Instruction::Load(0xF, kk), Instruction::SubtractInverted(0xF, x)],
+                };
+                let expected = if op.text == "<" { 0 } else { 1 };
+                (prefix, Instruction::SkipIfNotEqual(0xF, expected))
+            }
+            // vf = y - x, with vf ending up 1 when y >= x
+            (">", _) | ("<=", _) => {
+                let prefix = match y {
+                    Some(y) => vec![Instruction::LoadRegister(0xF, y), Instruction::Subtract(0xF, x)],
+                    None => vec![Instruction::Load(0xF, kk), Instruction::Subtract(0xF, x)],
+                };
+                let expected = if op.text == ">" { 0 } else { 1 };
+                (prefix, Instruction::SkipIfNotEqual(0xF, expected))
+            }
+            _ => return Err(self.error_at(&op, format!("unknown comparison \"{}\"", op.text))),
+        };
+        Ok(condition)
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.name_token()?;
+        let mut args = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            args.push(token.text);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+        self.macros.insert(name.text, Macro { args, body });
+        Ok(())
+    }
+
+    // a macro call is replaced by the macro's body with its arguments substituted in. the expanded
+    // tokens keep the position of the call so errors point at the line using the macro
+    fn expand_macro(&mut self, call: &Token) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return Err(self.error_at(call, "too many macro expansions, does a macro call itself?".to_string()));
+        }
+        let arg_count = self.macros[&call.text].args.len();
+        let mut values = Vec::new();
+        for _ in 0..arg_count {
+            values.push(self.next()?.text);
+        }
+        let definition = &self.macros[&call.text];
+        let expanded: Vec<Token> = definition.body.iter().map(|token| {
+            let text = match definition.args.iter().position(|arg| *arg == token.text) {
+                Some(index) => values[index].clone(),
+                None => token.text.clone(),
+            };
+            Token { text, line: call.line, column: call.column }
+        }).collect();
+        for token in expanded.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    // evaluate a { ... } calc expression. as in Octo there is no operator precedence, expressions
+    // are evaluated right to left unless parentheses say otherwise
+    fn calc(&mut self) -> Result<f64, AsmError> {
+        self.expect("{")?;
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "}" {
+                break;
+            }
+            tokens.push(token);
+        }
+        let mut pos = 0;
+        let value = self.expression(&tokens, &mut pos)?;
+        match tokens.get(pos) {
+            Some(token) => Err(self.error_at(token, format!("unexpected \"{}\" in expression", token.text))),
+            None => Ok(value),
+        }
+    }
+
+    fn expression(&self, tokens: &[Token], pos: &mut usize) -> Result<f64, AsmError> {
+        let lhs = self.term(tokens, pos)?;
+        let op = match tokens.get(*pos) {
+            Some(op) if op.text != ")" => op,
+            _ => return Ok(lhs),
+        };
+        *pos += 1;
+        let rhs = self.expression(tokens, pos)?;
+        let (a, b) = (lhs as i64, rhs as i64);
+        Ok(match op.text.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" | ">>" => {
+                // shifting by the width of the value or more, or by a negative amount, has no answer
+                let shifted = u32::try_from(b).ok()
+                    .and_then(|b| if op.text == "<<" { a.checked_shl(b) } else { a.checked_shr(b) });
+                match shifted {
+                    Some(value) => value as f64,
+                    None => return Err(self.error_at(op, format!("can't shift by {}", b))),
+                }
+            }
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            _ => return Err(self.error_at(op, format!("unknown operator \"{}\"", op.text))),
+        })
+    }
+
+    fn term(&self, tokens: &[Token], pos: &mut usize) -> Result<f64, AsmError> {
+        let token = match tokens.get(*pos) {
+            Some(token) => token,
+            None => return Err(self.error_at(&self.last, "expected a value".to_string())),
+        };
+        *pos += 1;
+        match token.text.as_str() {
+            "(" => {
+                let value = self.expression(tokens, pos)?;
+                match tokens.get(*pos) {
+                    Some(close) if close.text == ")" => {
+                        *pos += 1;
+                        Ok(value)
+                    }
+                    _ => Err(self.error_at(token, "missing closing parenthesis".to_string())),
+                }
+            }
+            "-" => Ok(-self.term(tokens, pos)?),
+            "~" => Ok(!(self.term(tokens, pos)? as i64) as f64),
+            "floor" => Ok(self.term(tokens, pos)?.floor()),
+            "HERE" => Ok(self.here as f64),
+            _ => match self.labels.get(&token.text) {
+                Some(&addr) => Ok(addr as f64),
+                None => self.number(token),
+            },
+        }
+    }
+
+    // an address for an instruction, labels that aren't defined yet are patched in at the end
+    fn emit_address(&mut self, instruction: fn(u16) -> Instruction, token: &Token) -> Result<(), AsmError> {
+        match self.address(token, 0xFFF)? {
+            Some(addr) => self.emit(instruction(addr)),
+            None => {
+                self.fixups.push(Fixup { addr: self.here, token: token.clone(), long: false });
+                self.emit(instruction(0))
+            }
+        }
+    }
+
+    // the value of a number, constant or label, or None if it names a label that isn't defined yet
+    fn address(&self, token: &Token, max: usize) -> Result<Option<u16>, AsmError> {
+        let value = if let Some(&addr) = self.labels.get(&token.text) {
+            addr as f64
+        } else if parse_number(&token.text).is_some() || self.constants.contains_key(&token.text) {
+            self.number(token)?
+        } else if is_name(&token.text) {
+            return Ok(None);
+        } else {
+            return Err(self.error_at(token, format!("expected an address, found \"{}\"", token.text)));
+        };
+        if value < 0.0 || value > max as f64 {
+            return Err(self.error_at(token, format!("address {} is out of range", value)));
+        }
+        Ok(Some(value as u16))
+    }
+
+    fn number(&self, token: &Token) -> Result<f64, AsmError> {
+        if let Some(&value) = self.constants.get(&token.text) {
+            return Ok(value);
+        }
+        match parse_number(&token.text) {
+            Some(value) => Ok(value as f64),
+            None => Err(self.error_at(token, format!("expected a number, found \"{}\"", token.text))),
+        }
+    }
+
+    fn byte(&self, token: &Token) -> Result<u8, AsmError> {
+        let value = self.number(token)?;
+        self.byte_value(value, token)
+    }
+
+    fn byte_value(&self, value: f64, token: &Token) -> Result<u8, AsmError> {
+        if !(-128.0..=255.0).contains(&value) {
+            return Err(self.error_at(token, format!("{} doesn't fit in a byte", value)));
+        }
+        Ok(value as i64 as u8)
+    }
+
+    fn next_nibble(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        let value = self.number(&token)?;
+        if !(0.0..=15.0).contains(&value) {
+            return Err(self.error_at(&token, format!("{} is out of range, expected 0 to 15", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn is_register(&self, token: &Token) -> bool {
+        self.aliases.contains_key(&token.text) || parse_register(&token.text).is_some()
+    }
+
+    fn register(&self, token: &Token) -> Result<u8, AsmError> {
+        match self.aliases.get(&token.text).copied().or_else(|| parse_register(&token.text)) {
+            Some(register) => Ok(register),
+            None => Err(self.error_at(token, format!("expected a register, found \"{}\"", token.text))),
+        }
+    }
+
+    fn next_register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        self.register(&token)
+    }
+
+    fn name_token(&mut self) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        if !is_name(&token.text) || parse_register(&token.text).is_some() {
+            return Err(self.error_at(&token, format!("\"{}\" is not a valid name", token.text)));
+        }
+        Ok(token)
+    }
+
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.last = token.clone();
+                Ok(token)
+            }
+            None => Err(self.error_at(&self.last, "unexpected end of file".to_string())),
+        }
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.text == text)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(self.error_at(&token, format!("expected \"{}\", found \"{}\"", text, token.text)));
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<(), AsmError> {
+        self.emit_word(instruction.encode())
+    }
+
+    fn emit_all(&mut self, instructions: &[Instruction]) -> Result<(), AsmError> {
+        instructions.iter().try_for_each(|&instruction| self.emit(instruction))
+    }
+
+    fn emit_word(&mut self, word: u16) -> Result<(), AsmError> {
+        let [high, low] = word.to_be_bytes();
+        self.emit_byte(high)?;
+        self.emit_byte(low)
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AsmError> {
+        if self.here >= CHIP8_RAM {
+            return Err(self.error_at(&self.last, "program is too large to fit in memory".to_string()));
+        }
+        let offset = self.here - PROGRAM_START;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn patch_jump(&mut self, addr: usize, target: usize, token: &Token) -> Result<(), AsmError> {
+        let offset = addr - PROGRAM_START;
+        let jump = self.jump(target, token)?;
+        self.rom[offset..offset + 2].copy_from_slice(&jump.encode().to_be_bytes());
+        Ok(())
+    }
+
+    // a jump made by a control statement, the target has to fit in the 12 bits of the opcode
+    fn jump(&self, target: usize, token: &Token) -> Result<Instruction, AsmError> {
+        if target > 0xFFF {
+            return Err(self.error_at(token, format!("\"{}\" jumps to {:#x}, which is past 0xFFF", token.text, target)));
+        }
+        Ok(Instruction::Jump(target as u16))
+    }
+
+    fn error_at(&self, token: &Token, message: String) -> AsmError {
+        AsmError { file: self.name.to_string(), line: token.line, column: token.column, message }
+    }
+}
+
+// the skip taken in the opposite case
+fn negate(skip: Instruction) -> Instruction {
+    match skip {
+        Instruction::SkipIfEqual(x, kk) => Instruction::SkipIfNotEqual(x, kk),
+        Instruction::SkipIfNotEqual(x, kk) => Instruction::SkipIfEqual(x, kk),
+        Instruction::SkipIfRegistersEqual(x, y) => Instruction::SkipIfRegistersNotEqual(x, y),
+        Instruction::SkipIfRegistersNotEqual(x, y) => Instruction::SkipIfRegistersEqual(x, y),
+        Instruction::SkipIfKeyPressed(x) => Instruction::SkipIfKeyNotPressed(x),
+        Instruction::SkipIfKeyNotPressed(x) => Instruction::SkipIfKeyPressed(x),
+        other => other,
+    }
+}
+
+// tokens are separated by whitespace, # starts a comment and braces always stand on their own
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut start = None;
+        for (i, c) in line.char_indices().chain(std::iter::once((line.len(), ' '))) {
+            let brace = c == '{' || c == '}';
+            if c.is_whitespace() || brace {
+                if let Some(start) = start.take() {
+                    tokens.push_back(Token { text: line[start..i].to_string(), line: number + 1, column: start + 1 });
+                }
+                if brace {
+                    tokens.push_back(Token { text: c.to_string(), line: number + 1, column: i + 1 });
+                }
+            } else if start.is_none() {
+                start = Some(i);
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => digit.to_digit(16).map(|digit| digit as u8),
+        _ => None,
+    }
+}
+
+fn is_name(text: &str) -> bool {
+    text.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+
+    // compile `if lhs op rhs then v2 := 1` and run it, returning whether the branch was taken
+    fn compare(lhs: u8, op: &str, rhs: u8, immediate: bool) -> bool {
+        let rhs_operand = if immediate { rhs.to_string() } else { "v1".to_string() };
+        let source = format!(": main v0 := {} v1 := {} v2 := 0 if v0 {} {} then v2 := 1 exit",
+                             lhs, rhs, op, rhs_operand);
+        let rom = compile(&source, "test.8o").unwrap();
+        let mut cpu = CPU::new(Default::default());
+        cpu.load(&rom);
+        assert!(cpu.run_frame([false; 16], 100).unwrap().exited, "{} didn't exit", source);
+        cpu.v_registers()[2] == 1
+    }
+
+    #[test]
+    fn control_jumps_past_0xfff() {
+        for source in [": main :org 0x1000 loop again",
+                       ": main :org 0xFFC loop while v0 == 0 again",
+                       ": main :org 0xFFC if v0 == 0 begin v0 := 1 end",
+                       ": main :org 0xFFA if v0 == 0 begin v0 := 1 else v0 := 2 end"].iter() {
+            let error = compile(source, "test.8o").unwrap_err();
+            assert!(error.message.ends_with("past 0xFFF"), "{}: {}", source, error);
+        }
+        assert!(compile(": main :org 0xFFE loop again", "test.8o").is_ok());
+    }
+
+    #[test]
+    fn calc_shifts_out_of_range() {
+        assert!(compile(":calc a { 1 << 63 } : main", "test.8o").is_ok());
+        for source in [":calc a { 1 << 64 } : main", ":calc a { 1 >> -1 } : main"].iter() {
+            let error = compile(source, "test.8o").unwrap_err();
+            assert!(error.message.starts_with("can't shift"), "{}: {}", source, error);
+        }
+    }
+
+    #[test]
+    fn comparisons() {
+        for &(lhs, rhs) in [(5, 3), (3, 5), (4, 4), (0, 255), (255, 0)].iter() {
+            for &immediate in [false, true].iter() {
+                assert_eq!(compare(lhs, "==", rhs, immediate), lhs == rhs, "{} == {}", lhs, rhs);
+                assert_eq!(compare(lhs, "!=", rhs, immediate), lhs != rhs, "{} != {}", lhs, rhs);
+                assert_eq!(compare(lhs, "<", rhs, immediate), lhs < rhs, "{} < {}", lhs, rhs);
+                assert_eq!(compare(lhs, ">", rhs, immediate), lhs > rhs, "{} > {}", lhs, rhs);
+                assert_eq!(compare(lhs, "<=", rhs, immediate), lhs <= rhs, "{} <= {}", lhs, rhs);
+                assert_eq!(compare(lhs, ">=", rhs, immediate), lhs >= rhs, "{} >= {}", lhs, rhs);
+            }
+        }
+    }
+}
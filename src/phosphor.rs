@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use crate::display::{Framebuffer, Palette};
+
+// a pixel that has faded this many frames is drawn as off. this keeps the number of colours a
+// faded frame can have (3 lit colours at every age plus the 4 of the palette) within the 256 a GIF
+// frame can hold
+const MAX_AGE: usize = 84;
+
+// CHIP8 programs erase and redraw sprites with XOR, so moving sprites flicker as they are shown
+// half drawn. these filters emulate the persistence of a CRT's phosphor to hide the flicker
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phosphor {
+    // pixels turn on straight away and fade out, keeping this much of their brightness every frame
+    Fade(f32),
+    // a pixel is shown lit if it was lit in any of the last this many frames
+    Blend(usize),
+}
+
+impl Default for Phosphor {
+    fn default() -> Phosphor {
+        Phosphor::Fade(0.6)
+    }
+}
+
+// parsed from fade or blend, optionally followed by a colon and the amount, e.g. fade:0.8 or blend:3
+impl FromStr for Phosphor {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Phosphor, String> {
+        let (mode, amount) = match text.split_once(':') {
+            Some((mode, amount)) => (mode, Some(amount)),
+            None => (text, None),
+        };
+        match (mode, amount) {
+            ("fade", None) => Ok(Phosphor::default()),
+            ("fade", Some(amount)) => match amount.parse::<f32>() {
+                Ok(amount) if (0.0..1.0).contains(&amount) => Ok(Phosphor::Fade(amount)),
+                _ => Err(format!("the fade amount has to be at least 0 and less than 1, not {}", amount)),
+            },
+            ("blend", None) => Ok(Phosphor::Blend(2)),
+            ("blend", Some(amount)) => match amount.parse::<usize>() {
+                Ok(frames) if frames > 0 => Ok(Phosphor::Blend(frames)),
+                _ => Err(format!("the number of frames to blend has to be at least 1, not {}", amount)),
+            },
+            _ => Err(format!("unknown phosphor filter \"{}\", expected fade or blend", text)),
+        }
+    }
+}
+
+impl fmt::Display for Phosphor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Phosphor::Fade(amount) => write!(f, "fade:{}", amount),
+            Phosphor::Blend(frames) => write!(f, "blend:{}", frames),
+        }
+    }
+}
+
+// runs frames through a phosphor filter, it has to be given every 60Hz frame to fade at the right
+// speed
+pub struct PhosphorFilter {
+    phosphor: Phosphor,
+    // for fading, the planes every pixel was last lit in and how many frames ago that was
+    lit: Vec<(u8, usize)>,
+    // for blending, the last frames shown
+    history: VecDeque<Framebuffer>,
+}
+
+impl PhosphorFilter {
+    pub fn new(phosphor: Phosphor) -> PhosphorFilter {
+        PhosphorFilter {
+            phosphor,
+            lit: Vec::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    // forget the history, after the display has jumped to another state like a rewind or a load
+    pub fn reset(&mut self) {
+        self.lit.clear();
+        self.history.clear();
+    }
+
+    pub fn apply(&mut self, frame: &Framebuffer, palette: &Palette) -> Framebuffer {
+        match self.phosphor {
+            Phosphor::Fade(amount) => self.fade(frame, palette, amount),
+            Phosphor::Blend(frames) => self.blend(frame, frames),
+        }
+    }
+
+    fn fade(&mut self, frame: &Framebuffer, palette: &Palette, amount: f32) -> Framebuffer {
+        if self.lit.len() != frame.pixels().len() {
+            self.lit = vec![(0, 0); frame.pixels().len()];
+        }
+
+        let off = palette[0];
+        let mix = |channel: u8, off: u8, weight: f32| (off as f32 + (channel as f32 - off as f32) * weight).round() as u8;
+        let mut colours = Vec::with_capacity(frame.pixels().len());
+        for (&pixel, lit) in frame.pixels().iter().zip(self.lit.iter_mut()) {
+            *lit = match *lit {
+                _ if pixel != 0 => (pixel, 0),
+                (0, _) => (0, 0),
+                (_, age) if age >= MAX_AGE => (0, 0),
+                (planes, age) => (planes, age + 1),
+            };
+            let (planes, age) = *lit;
+            let (r, g, b) = palette[planes as usize % palette.len()];
+            let weight = amount.powi(age as i32);
+            colours.push((mix(r, off.0, weight), mix(g, off.1, weight), mix(b, off.2, weight)));
+        }
+        frame.clone().with_colours(colours)
+    }
+
+    fn blend(&mut self, frame: &Framebuffer, frames: usize) -> Framebuffer {
+        // frames of another resolution can't be blended with this one
+        self.history.retain(|old| old.width() == frame.width() && old.height() == frame.height());
+        self.history.push_back(frame.clone());
+        while self.history.len() > frames {
+            self.history.pop_front();
+        }
+
+        let mut pixels = vec![0; frame.pixels().len()];
+        for old in self.history.iter() {
+            for (pixel, &old) in pixels.iter_mut().zip(old.pixels()) {
+                *pixel |= old;
+            }
+        }
+        Framebuffer::from_pixels(frame.width(), frame.height(), pixels)
+    }
+}
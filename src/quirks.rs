@@ -0,0 +1,114 @@
+// the CHIP8 instruction set was never properly standardised and the interpreters that followed the
+// original COSMAC VIP one changed the behaviour of a handful of instructions, these flags select
+// which interpretation the CPU follows so ROMs written for different interpreters run correctly
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quirks {
+    // 8xy6/8xyE shift Vy and store the result in Vx instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    // how far Fx55/Fx65 move I once the registers are stored/loaded
+    pub load_store_increment: IndexIncrement,
+    // Bnnn jumps to xnn + Vx instead of nnn + V0
+    pub jump_uses_vx: bool,
+    // 8xy1/8xy2/8xy3 reset VF to 0
+    pub vf_reset: bool,
+    // sprites drawn past the edge of the screen are clipped instead of wrapping around
+    pub clip_sprites: bool,
+    // Dxyn waits for the next vertical blank before drawing, limiting draws to 60 per second
+    pub display_wait: bool,
+}
+
+// the interpreters disagree on where Fx55/Fx65 leave I
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IndexIncrement {
+    // I is left alone, as SUPER-CHIP 1.1 does
+    #[default]
+    Unchanged,
+    // I points at the last register stored/loaded, as CHIP-48 does
+    ByX,
+    // I points past the last register stored/loaded, as the COSMAC VIP and XO-CHIP do
+    ByXPlusOne,
+}
+
+// instructions per frame used when no profile is selected
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 11;
+
+// the named interpreters we have quirk presets for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuirkProfile {
+    CosmacVip,
+    Chip48,
+    Schip11,
+    XoChip,
+}
+
+impl QuirkProfile {
+    // look up a profile from the name given on the command line
+    pub fn from_name(name: &str) -> Option<QuirkProfile> {
+        match name.to_lowercase().as_str() {
+            "vip" | "cosmac" | "cosmac-vip" | "chip8" | "chip-8" => Some(QuirkProfile::CosmacVip),
+            "chip48" | "chip-48" => Some(QuirkProfile::Chip48),
+            "schip" | "schip11" | "schip-1.1" | "superchip" => Some(QuirkProfile::Schip11),
+            "xochip" | "xo-chip" | "octo" => Some(QuirkProfile::XoChip),
+            _ => None,
+        }
+    }
+
+    // look up a profile from a platform id of the community CHIP-8 database. modern CHIP-8
+    // interpreters behave like XO-CHIP does
+    pub fn from_platform(platform: &str) -> Option<QuirkProfile> {
+        match platform {
+            "originalChip8" | "hybridVIP" | "chip8x" => Some(QuirkProfile::CosmacVip),
+            "chip48" => Some(QuirkProfile::Chip48),
+            "superchip1" | "superchip" => Some(QuirkProfile::Schip11),
+            "xochip" | "modernChip8" => Some(QuirkProfile::XoChip),
+            _ => None,
+        }
+    }
+
+    // how many instructions the interpreter executes every 60Hz frame
+    pub fn instructions_per_frame(self) -> usize {
+        match self {
+            QuirkProfile::CosmacVip => 11,
+            QuirkProfile::Chip48 => 30,
+            QuirkProfile::Schip11 => 30,
+            QuirkProfile::XoChip => 1000,
+        }
+    }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            QuirkProfile::CosmacVip => Quirks {
+                shift_uses_vy: true,
+                load_store_increment: IndexIncrement::ByXPlusOne,
+                jump_uses_vx: false,
+                vf_reset: true,
+                clip_sprites: true,
+                display_wait: true,
+            },
+            QuirkProfile::Chip48 => Quirks {
+                shift_uses_vy: false,
+                load_store_increment: IndexIncrement::ByX,
+                jump_uses_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+                display_wait: false,
+            },
+            QuirkProfile::Schip11 => Quirks {
+                shift_uses_vy: false,
+                load_store_increment: IndexIncrement::Unchanged,
+                jump_uses_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+                display_wait: false,
+            },
+            QuirkProfile::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_increment: IndexIncrement::ByXPlusOne,
+                jump_uses_vx: false,
+                vf_reset: false,
+                clip_sprites: false,
+                display_wait: false,
+            },
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::iter;
+use std::path::Path;
+use crate::beeper::Beeper;
+use crate::display::{Colour, Framebuffer, Palette, DEFAULT_PALETTE, HIRES_HEIGHT, HIRES_WIDTH};
+use crate::frontend::Tone;
+
+// the audio track is 16 bit mono PCM at 44.1kHz, exactly 735 samples every 60Hz frame
+const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_FRAME: usize = SAMPLE_RATE as usize / 60;
+
+// the shortest delay browsers show GIF frames for without slowing them down, in hundredths of a second
+const MIN_GIF_DELAY: u64 = 2;
+
+// the most times bigger than the high resolution display a recording can be, GIFs can't be more
+// than 65535 pixels wide
+pub const MAX_RECORD_SCALE: usize = u16::MAX as usize / HIRES_WIDTH;
+
+// records every frame of gameplay to an animated GIF or a Y4M video, picked by the extension of
+// the file, and the beeper to a WAV file next to it. recordings are always the size of the high
+// resolution display, low resolution frames are doubled up so switching resolution mid-game works
+pub struct Recorder {
+    video: Option<Video>,
+    audio: Option<Wav>,
+    scale: usize,
+}
+
+enum Video {
+    Gif(Gif),
+    Y4m(BufWriter<File>),
+}
+
+impl Recorder {
+    pub fn create(path: &Path, scale: usize) -> io::Result<Recorder> {
+        if !(1..=MAX_RECORD_SCALE).contains(&scale) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("recordings can only be 1 to {} times bigger", MAX_RECORD_SCALE)));
+        }
+        let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+        let (width, height) = (HIRES_WIDTH * scale, HIRES_HEIGHT * scale);
+        let video = match extension.as_deref() {
+            Some("gif") => Video::Gif(Gif::create(path, width, height)?),
+            Some("y4m") => {
+                let mut file = BufWriter::new(File::create(path)?);
+                writeln!(file, "YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444", width, height)?;
+                Video::Y4m(file)
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "recordings have to be .gif or .y4m files")),
+        };
+        Ok(Recorder {
+            video: Some(video),
+            audio: Some(Wav::create(&path.with_extension("wav"))?),
+            scale,
+        })
+    }
+
+    // add a 60Hz frame of video and audio, tone is None while the beeper is silent
+    pub fn frame(&mut self, frame: &Framebuffer, palette: &Palette, tone: Option<Tone>) -> io::Result<()> {
+        // scale every pixel up to the size of the recording, as the colour it is shown in
+        let scale = self.scale * HIRES_WIDTH / frame.width();
+        let mut pixels = Vec::with_capacity(frame.pixels().len() * scale * scale);
+        for y in 0..frame.height() * scale {
+            for x in 0..frame.width() {
+                pixels.extend(iter::repeat_n(frame.colour(x, y / scale, palette), scale));
+            }
+        }
+
+        match self.video.as_mut() {
+            Some(Video::Gif(gif)) => gif.frame(pixels)?,
+            Some(Video::Y4m(file)) => write_y4m_frame(file, &pixels)?,
+            None => {}
+        }
+        if let Some(audio) = self.audio.as_mut() {
+            audio.frame(tone)?;
+        }
+        Ok(())
+    }
+
+    // write out everything still buffered and close the files, recording more frames afterwards
+    // does nothing
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self.video.take() {
+            Some(Video::Gif(gif)) => gif.finish()?,
+            Some(Video::Y4m(mut file)) => file.flush()?,
+            None => {}
+        }
+        if let Some(audio) = self.audio.take() {
+            audio.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+// an animated GIF where a frame that is the same as the one before it only extends how long that
+// one is shown. GIF delays are in hundredths of a second so they are rounded to keep the total
+// time right, and as browsers slow down frames shown for less than 2 hundredths of a second a
+// frame that short is replaced by the one after it. every frame has a palette of its own with the
+// colours in it, as themes and the phosphor filter can show any colour
+struct Gif {
+    encoder: gif::Encoder<BufWriter<File>>,
+    width: u16,
+    height: u16,
+    // the frame waiting to be written, and how many 60Hz frames it has been shown for
+    pending: Option<(Vec<Colour>, u64)>,
+    // the number of 60Hz frames written so far
+    frames: u64,
+}
+
+impl Gif {
+    fn create(path: &Path, width: usize, height: usize) -> io::Result<Gif> {
+        let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           format!("a {}x{} GIF is too big, they can be 65535x65535 at most", width, height))),
+        };
+        let mut encoder = gif::Encoder::new(BufWriter::new(File::create(path)?), width, height,
+                                            &palette_bytes(&DEFAULT_PALETTE)).map_err(io::Error::other)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+        Ok(Gif {
+            encoder,
+            width,
+            height,
+            pending: None,
+            frames: 0,
+        })
+    }
+
+    fn frame(&mut self, pixels: Vec<Colour>) -> io::Result<()> {
+        if let Some((pending, count)) = self.pending.as_mut() {
+            if *pending == pixels {
+                *count += 1;
+                return Ok(());
+            }
+            if centiseconds(self.frames + *count) - centiseconds(self.frames) < MIN_GIF_DELAY {
+                *pending = pixels;
+                *count += 1;
+                return Ok(());
+            }
+        }
+        self.flush()?;
+        self.pending = Some((pixels, 1));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let (pixels, count) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        let (palette, pixels) = index_colours(&pixels);
+        let start = centiseconds(self.frames);
+        self.frames += count;
+        let frame = gif::Frame {
+            width: self.width,
+            height: self.height,
+            // only the last frame can be shorter than the minimum
+            delay: (centiseconds(self.frames) - start).clamp(MIN_GIF_DELAY, u16::MAX as u64) as u16,
+            palette: Some(palette.iter().flat_map(|&(r, g, b)| [r, g, b]).collect()),
+            buffer: Cow::Owned(pixels),
+            ..gif::Frame::default()
+        };
+        self.encoder.write_frame(&frame).map_err(io::Error::other)
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.flush()?;
+        self.encoder.into_inner().map_err(io::Error::other)?.flush()
+    }
+}
+
+// the time at the start of a 60Hz frame, in hundredths of a second
+fn centiseconds(frames: u64) -> u64 {
+    frames * 100 / 60
+}
+
+fn palette_bytes(palette: &Palette) -> Vec<u8> {
+    palette.iter().flat_map(|&(r, g, b)| vec![r, g, b]).collect()
+}
+
+// the colours used in a frame and the index of every pixel's colour among them. a GIF palette
+// holds at most 256 colours, past that pixels are given the closest colour already in it
+fn index_colours(pixels: &[Colour]) -> (Vec<Colour>, Vec<u8>) {
+    let mut palette = Vec::new();
+    let mut indices = HashMap::new();
+    let distance = |a: Colour, b: Colour| {
+        let channel = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+    };
+    let pixels = pixels.iter().map(|&colour| {
+        *indices.entry(colour).or_insert_with(|| {
+            if palette.len() < 256 {
+                palette.push(colour);
+                (palette.len() - 1) as u8
+            } else {
+                (0..palette.len()).min_by_key(|&index| distance(palette[index], colour)).unwrap_or(0) as u8
+            }
+        })
+    }).collect();
+    (palette, pixels)
+}
+
+// a frame of 4:4:4 Y4M, every pixel's colour converted to BT.601 YCbCr a plane at a time
+fn write_y4m_frame(file: &mut BufWriter<File>, pixels: &[Colour]) -> io::Result<()> {
+    let mut colours = HashMap::new();
+    let mut colour = |(r, g, b): Colour| *colours.entry((r, g, b)).or_insert_with(|| {
+        let (r, g, b) = (r as f32, g as f32, b as f32);
+        let y = 16.0 + 0.257 * r + 0.504 * g + 0.098 * b;
+        let cb = 128.0 - 0.148 * r - 0.291 * g + 0.439 * b;
+        let cr = 128.0 + 0.439 * r - 0.368 * g - 0.071 * b;
+        (y.round() as u8, cb.round() as u8, cr.round() as u8)
+    });
+    let converted: Vec<Colour> = pixels.iter().map(|&pixel| colour(pixel)).collect();
+
+    file.write_all(b"FRAME\n")?;
+    let y: Vec<u8> = converted.iter().map(|colour| colour.0).collect();
+    let cb: Vec<u8> = converted.iter().map(|colour| colour.1).collect();
+    let cr: Vec<u8> = converted.iter().map(|colour| colour.2).collect();
+    file.write_all(&y)?;
+    file.write_all(&cb)?;
+    file.write_all(&cr)
+}
+
+// a WAV file whose header is filled in with the length of the data once it is finished
+struct Wav {
+    file: BufWriter<File>,
+    beeper: Beeper,
+    samples: u32,
+}
+
+impl Wav {
+    fn create(path: &Path) -> io::Result<Wav> {
+        let mut file = BufWriter::new(File::create(path)?);
+        // the sizes in the RIFF and data chunk headers are written as 0 until we know them
+        file.write_all(b"RIFF\0\0\0\0WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?; // PCM
+        file.write_all(&1u16.to_le_bytes())?; // mono
+        file.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        file.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?; // bytes per second
+        file.write_all(&2u16.to_le_bytes())?; // bytes per sample
+        file.write_all(&16u16.to_le_bytes())?; // bits per sample
+        file.write_all(b"data\0\0\0\0")?;
+        Ok(Wav {
+            file,
+            beeper: Beeper::new(SAMPLE_RATE),
+            samples: 0,
+        })
+    }
+
+    fn frame(&mut self, tone: Option<Tone>) -> io::Result<()> {
+        let mut samples = [0.0; SAMPLES_PER_FRAME];
+        if let Some(tone) = tone {
+            self.beeper.set_pattern(tone.pattern, tone.pitch);
+            self.beeper.fill(&mut samples);
+        }
+        for sample in samples.iter() {
+            self.file.write_all(&((sample * i16::MAX as f32) as i16).to_le_bytes())?;
+        }
+        self.samples += SAMPLES_PER_FRAME as u32;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        let data_size = self.samples * 2;
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(36 + data_size).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&data_size.to_le_bytes())?;
+        self.file.flush()
+    }
+}
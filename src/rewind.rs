@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+use crate::cpu::CPU;
+
+// rewind snapshots never leave the process so they don't need a real ROM hash
+const REWIND_HASH: u64 = 0;
+
+// keeps a bounded history of per-frame CPU states to step back through. only the newest state is
+// kept in full, every older frame is stored as the difference needed to turn the state after it back
+// into it. consecutive frames barely differ, so each difference is the XOR of the two states with the
+// runs of unchanged (zero) bytes squashed down
+pub struct Rewinder {
+    capacity: usize,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewinder {
+    // the capacity is the number of frames that can be stepped back through
+    pub fn new(capacity: usize) -> Rewinder {
+        Rewinder {
+            capacity,
+            latest: None,
+            deltas: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    // the history needed to rewind the given number of seconds of 60Hz frames
+    pub fn with_seconds(seconds: usize) -> Rewinder {
+        Rewinder::new(seconds * 60)
+    }
+
+    // record the state of the CPU at the end of a frame. with no history to keep there is no point
+    // saving the state at all
+    pub fn push(&mut self, cpu: &CPU) {
+        if self.capacity == 0 {
+            return;
+        }
+        let state = cpu.save_state(REWIND_HASH);
+        if let Some(latest) = self.latest.take() {
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(encode_delta(&state, &latest));
+        }
+        self.latest = Some(state);
+    }
+
+    // restore the CPU to the frame before the most recent one, returning false if there is no
+    // more history to go back through
+    pub fn step_back(&mut self, cpu: &mut CPU) -> bool {
+        let (delta, latest) = match (self.deltas.pop_back(), self.latest.as_mut()) {
+            (Some(delta), Some(latest)) => (delta, latest),
+            _ => return false,
+        };
+        apply_delta(latest, &delta);
+        cpu.load_state(latest, REWIND_HASH).is_ok()
+    }
+
+    // the number of frames that can currently be stepped back through
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+}
+
+// a delta is a list of (unchanged run length, changed run length, changed bytes XORed) records,
+// with both lengths written as LEB128 varints
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut pos = 0;
+    while pos < from.len() {
+        let unchanged = from[pos..].iter().zip(&to[pos..]).take_while(|(a, b)| a == b).count();
+        pos += unchanged;
+        let changed = from[pos..].iter().zip(&to[pos..]).take_while(|(a, b)| a != b).count();
+        write_varint(&mut delta, unchanged);
+        write_varint(&mut delta, changed);
+        delta.extend(from[pos..pos + changed].iter().zip(&to[pos..pos + changed]).map(|(a, b)| a ^ b));
+        pos += changed;
+    }
+    delta
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut delta_pos = 0;
+    let mut pos = 0;
+    while delta_pos < delta.len() {
+        pos += read_varint(delta, &mut delta_pos);
+        let changed = read_varint(delta, &mut delta_pos);
+        for (byte, xor) in state[pos..pos + changed].iter_mut().zip(&delta[delta_pos..delta_pos + changed]) {
+            *byte ^= xor;
+        }
+        pos += changed;
+        delta_pos += changed;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_restores_the_other_state() {
+        let older: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        let mut newer = older.clone();
+        // changes at both ends, a run of changes and an unchanged run too long for one varint byte
+        newer[0] ^= 0xFF;
+        newer[5..9].copy_from_slice(&[1, 2, 3, 4]);
+        newer[600] = newer[600].wrapping_add(1);
+        newer[999] = 0x42;
+
+        let delta = encode_delta(&newer, &older);
+        let mut state = newer.clone();
+        apply_delta(&mut state, &delta);
+        assert_eq!(state, older);
+        apply_delta(&mut state, &delta);
+        assert_eq!(state, newer);
+    }
+
+    #[test]
+    fn identical_states_need_no_changes() {
+        let state = vec![0x12; 300];
+        let delta = encode_delta(&state, &state);
+        let mut restored = state.clone();
+        apply_delta(&mut restored, &delta);
+        assert_eq!(restored, state);
+    }
+
+    #[test]
+    fn steps_back_to_the_saved_state() {
+        let mut rewinder = Rewinder::new(2);
+        let mut cpu = CPU::new(Default::default());
+        // v0 := 0x12, i := 0x300, save v0 to memory
+        cpu.load(&[0x60, 0x12, 0xA3, 0x00, 0xF0, 0x55]);
+        let before = cpu.save_state(REWIND_HASH);
+        rewinder.push(&cpu);
+        cpu.run_frame([false; 16], 3).unwrap();
+        rewinder.push(&cpu);
+
+        assert!(rewinder.step_back(&mut cpu));
+        assert_eq!(cpu.save_state(REWIND_HASH), before);
+        assert!(!rewinder.step_back(&mut cpu));
+    }
+
+    #[test]
+    fn no_capacity_keeps_nothing() {
+        let mut rewinder = Rewinder::new(0);
+        let mut cpu = CPU::new(Default::default());
+        rewinder.push(&cpu);
+        rewinder.push(&cpu);
+        assert!(rewinder.is_empty());
+        assert!(!rewinder.step_back(&mut cpu));
+    }
+}
@@ -0,0 +1,69 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use crate::assembler::AsmError;
+use crate::cartridge::{self, CartridgeError, CartridgeOptions};
+use crate::cpu::{CHIP8_RAM, PROGRAM_START};
+use crate::octo;
+use crate::rom_db::{RomDatabase, RomInfo};
+
+// programs are loaded at PROGRAM_START and can fill the rest of the XO-CHIP address space
+pub const MAX_ROM_SIZE: usize = CHIP8_RAM - PROGRAM_START;
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    Compile(AsmError),
+    Cartridge(CartridgeError),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(err) => write!(f, "{}", err),
+            RomError::Compile(err) => write!(f, "{}", err),
+            RomError::Cartridge(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+// a program ready to be handed to CPU::load, along with what is known about how to run it
+pub struct Rom {
+    pub data: Vec<u8>,
+    // the runtime options recommended by an Octo cartridge, None for plain ROMs and sources
+    pub options: Option<CartridgeOptions>,
+    // what the ROM database knows about the program, found by the SHA-1 of the ROM
+    pub info: Option<RomInfo>,
+}
+
+impl Rom {
+    // read in a ROM file, Octo sources (.8o) and Octo cartridge GIFs are compiled in-process
+    pub fn load(filename: &str, database: &RomDatabase) -> Result<Rom, RomError> {
+        let data = fs::read(filename).map_err(RomError::Io)?;
+        Rom::from_bytes(data, filename, database)
+    }
+
+    // the same as load for a file that has already been read, the name is used to recognise Octo
+    // sources and in compile errors
+    pub fn from_bytes(data: Vec<u8>, name: &str, database: &RomDatabase) -> Result<Rom, RomError> {
+        let (mut data, options) = if name.ends_with(".8o") {
+            let source = String::from_utf8_lossy(&data);
+            (octo::compile(&source, name).map_err(RomError::Compile)?, None)
+        } else if cartridge::is_cartridge(&data) {
+            let cartridge = cartridge::load(&data, name).map_err(RomError::Cartridge)?;
+            (cartridge.rom, Some(cartridge.options))
+        } else {
+            (data, None)
+        };
+
+        data.truncate(MAX_ROM_SIZE);
+        let info = database.lookup(&data).cloned();
+        Ok(Rom { data, options, info })
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+}
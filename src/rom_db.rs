@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+use crate::display::{parse_colour, Palette};
+use crate::quirks::QuirkProfile;
+
+// the database bundled with the emulator, in the same format as the programs.json of the community
+// database at https://github.com/chip-8/chip-8-database so entries can be copied between the two
+const BUNDLED_DATABASE: &str = include_str!("../data/rom_db.json");
+
+// what is known about a ROM, anything the database doesn't say is left empty
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    // the interpreter the ROM was written for, the first platform listed that we have a profile for
+    pub platform: Option<QuirkProfile>,
+    pub instructions_per_frame: Option<usize>,
+    // what the keys do, e.g. ("up", 5)
+    pub keys: Vec<(String, u8)>,
+    pub palette: Option<Palette>,
+}
+
+// ROM metadata keyed by the SHA-1 of the ROM
+#[derive(Default)]
+pub struct RomDatabase {
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDatabase {
+    pub fn bundled() -> RomDatabase {
+        let mut database = RomDatabase::default();
+        // the bundled database is checked in, it can only fail to parse if someone broke it
+        database.merge(BUNDLED_DATABASE).expect("bundled ROM database is invalid");
+        database
+    }
+
+    // add the programs from a database in the community programs.json format, replacing any
+    // entries for the same ROMs
+    pub fn merge(&mut self, json: &str) -> Result<(), String> {
+        let programs: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        let programs = match programs.as_array() {
+            Some(programs) => programs,
+            None => return Err("expected a list of programs".to_string()),
+        };
+        for program in programs {
+            let title = program.get("title").and_then(Value::as_str).unwrap_or_default();
+            let authors: Vec<String> = program.get("authors").and_then(Value::as_array).into_iter().flatten()
+                .filter_map(Value::as_str).map(str::to_string).collect();
+            let roms = match program.get("roms").and_then(Value::as_object) {
+                Some(roms) => roms,
+                None => continue,
+            };
+            for (hash, rom) in roms {
+                self.roms.insert(hash.to_lowercase(), rom_info(title, &authors, rom));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.roms.get(&sha1_hex(rom))
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+// lowercase hex SHA-1 of a ROM, the key used by the database
+pub fn sha1_hex(rom: &[u8]) -> String {
+    Sha1::digest(rom).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn rom_info(title: &str, authors: &[String], rom: &Value) -> RomInfo {
+    let platform = rom.get("platforms").and_then(Value::as_array).into_iter().flatten()
+        .filter_map(Value::as_str).find_map(QuirkProfile::from_platform);
+
+    let mut keys: Vec<(String, u8)> = rom.get("keys").and_then(Value::as_object).into_iter().flatten()
+        .filter_map(|(action, key)| Some((action.clone(), key.as_u64()? as u8)))
+        .collect();
+    keys.sort_by_key(|&(_, key)| key);
+
+    // two colours only cover the first plane, the others reuse the foreground
+    let colours: Vec<(u8, u8, u8)> = rom.get("colors").and_then(|colors| colors.get("pixels"))
+        .and_then(Value::as_array).into_iter().flatten()
+        .filter_map(Value::as_str).filter_map(parse_colour).collect();
+    let palette = match colours.as_slice() {
+        [off, on] => Some([*off, *on, *on, *on]),
+        [off, plane1, plane2, both, ..] => Some([*off, *plane1, *plane2, *both]),
+        _ => None,
+    };
+
+    RomInfo {
+        title: title.to_string(),
+        authors: authors.to_vec(),
+        platform,
+        instructions_per_frame: rom.get("tickrate").and_then(Value::as_u64).map(|rate| rate as usize),
+        keys,
+        palette,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_database_parses() {
+        RomDatabase::bundled();
+    }
+
+    #[test]
+    fn lookup_by_sha1() {
+        // the SHA-1 of "abc" from FIPS 180, given in uppercase to check keys are matched in any case
+        let mut database = RomDatabase::default();
+        database.merge(r#"[{"title": "ABC", "roms": {"A9993E364706816ABA3E25717850C26C9CD0D89D": {"tickrate": 20}}}]"#).unwrap();
+        let info = database.lookup(b"abc").unwrap();
+        assert_eq!((info.title.as_str(), info.instructions_per_frame), ("ABC", Some(20)));
+        assert!(database.lookup(b"abd").is_none());
+    }
+
+    #[test]
+    fn later_databases_replace_earlier_entries() {
+        let mut database = RomDatabase::default();
+        database.merge(r#"[{"title": "Old", "roms": {"a9993e364706816aba3e25717850c26c9cd0d89d": {}}}]"#).unwrap();
+        database.merge(r#"[{"title": "New", "roms": {"a9993e364706816aba3e25717850c26c9cd0d89d": {}}}]"#).unwrap();
+        assert_eq!(database.len(), 1);
+        assert_eq!(database.lookup(b"abc").unwrap().title, "New");
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+
+// save states start with a magic number and a format version, followed by a hash of the ROM they
+// were taken from so a state can't be loaded into a different program
+pub const STATE_MAGIC: [u8; 4] = *b"C8SS";
+pub const STATE_VERSION: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    RomMismatch,
+    Truncated,
+    InvalidValue,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "Not a save state file"),
+            StateError::UnsupportedVersion(version) => write!(f, "Unsupported save state version {}", version),
+            StateError::RomMismatch => write!(f, "Save state was taken from a different ROM"),
+            StateError::Truncated => write!(f, "Save state is truncated"),
+            StateError::InvalidValue => write!(f, "Save state contains an invalid value"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+// 64 bit FNV-1a hash of the ROM, enough to tell programs apart without pulling in a hashing crate
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// all values are written big endian
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new(rom_hash: u64) -> StateWriter {
+        let mut writer = StateWriter { data: Vec::new() };
+        writer.bytes(&STATE_MAGIC);
+        writer.u8(STATE_VERSION);
+        writer.u64(rom_hash);
+        writer
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.data.extend_from_slice(value);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    // check the header of the state before handing back a reader positioned after it
+    pub fn new(data: &'a [u8], rom_hash: u64) -> Result<StateReader<'a>, StateError> {
+        let mut reader = StateReader { data };
+        if reader.bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = reader.u8()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        if reader.u64()? != rom_hash {
+            return Err(StateError::RomMismatch);
+        }
+        Ok(reader)
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidValue),
+        }
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        let mut value = [0; 4];
+        value.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_be_bytes(value))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        let mut value = [0; 8];
+        value.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_be_bytes(value))
+    }
+
+    // array fields are copied straight into their destination
+    pub fn fill(&mut self, destination: &mut [u8]) -> Result<(), StateError> {
+        destination.copy_from_slice(self.bytes(destination.len())?);
+        Ok(())
+    }
+
+    // a state with anything left over after the last field wasn't written by this version
+    pub fn finish(self) -> Result<(), StateError> {
+        if !self.data.is_empty() {
+            return Err(StateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
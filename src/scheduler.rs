@@ -0,0 +1,44 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// if we fall this many frames behind (a stall, the machine going to sleep) give up on catching up
+const MAX_FRAMES_BEHIND: u32 = 5;
+
+// a display refresh this close to the end of the frame, as a fraction of a frame, is taken as the
+// end of it
+const VSYNC_TOLERANCE: u32 = 4;
+
+// paces the main loop against wall clock time. each frame is scheduled relative to the deadline of
+// the previous one rather than to when we woke up, so oversleeping doesn't accumulate into drift
+pub struct FrameScheduler {
+    frame_duration: Duration,
+    next_frame: Instant,
+}
+
+impl FrameScheduler {
+    pub fn new(frames_per_second: u32) -> FrameScheduler {
+        let frame_duration = Duration::from_secs(1) / frames_per_second;
+        FrameScheduler {
+            frame_duration,
+            next_frame: Instant::now() + frame_duration,
+        }
+    }
+
+    // sleep until the current frame is over. vsynced is whether the frame was just shown after
+    // waiting for the display to refresh, which on a display running at about our rate has already
+    // paced it. sleeping as well would push every frame into the refresh after
+    pub fn wait(&mut self, vsynced: bool) {
+        let now = Instant::now();
+        if vsynced && now + self.frame_duration / VSYNC_TOLERANCE >= self.next_frame {
+            // follow the display from here on so the frames stay in step with its refreshes
+            self.next_frame = now + self.frame_duration;
+            return;
+        }
+        if now < self.next_frame {
+            thread::sleep(self.next_frame - now);
+        } else if now - self.next_frame > self.frame_duration * MAX_FRAMES_BEHIND {
+            self.next_frame = now;
+        }
+        self.next_frame += self.frame_duration;
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::display::{Framebuffer, Palette};
+use crate::image::encode_png;
+
+// save a frame of the display as a PNG in the given directory, creating it if needed, and return
+// the path written to. screenshots are scaled up by the given factor
+pub fn save_screenshot(frame: &Framebuffer, palette: &Palette, scale: usize, directory: &Path, rom_file: &str)
+                       -> io::Result<PathBuf> {
+    let path = capture_path(directory, rom_file, "png")?;
+    fs::write(&path, encode_png(frame, palette, scale))?;
+    Ok(path)
+}
+
+// a new file in the given directory for a screenshot or recording, creating the directory if
+// needed. files are named after the ROM and the time they were taken, e.g.
+// pong-2026-10-18-07-50-40.png
+pub fn capture_path(directory: &Path, rom_file: &str, extension: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(directory)?;
+    let name = Path::new(rom_file).file_stem().map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "capture".to_string());
+    let stem = format!("{}-{}", name, timestamp(SystemTime::now()));
+
+    // taking two in the same second numbers the later ones instead of overwriting
+    let mut path = directory.join(format!("{}.{}", stem, extension));
+    let mut count = 1;
+    while path.exists() {
+        count += 1;
+        path = directory.join(format!("{}-{}.{}", stem, count, extension));
+    }
+    Ok(path)
+}
+
+// the UTC date and time as year-month-day-hour-minute-second
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+    let (year, month, day) = civil_date(days as i64);
+    format!("{:04}-{:02}-{:02}-{:02}-{:02}-{:02}", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+// the calendar date a number of days after 1970-01-01, using Howard Hinnant's civil_from_days
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
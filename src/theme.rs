@@ -0,0 +1,42 @@
+use crate::display::{parse_colour, Palette, DEFAULT_PALETTE};
+
+// a named palette the display can be shown in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub palette: Palette,
+}
+
+impl Theme {
+    pub fn new(name: &str, palette: Palette) -> Theme {
+        Theme { name: name.to_string(), palette }
+    }
+}
+
+// the themes that come with the emulator, in the order the theme hotkey cycles through them. each
+// is off, plane 1 (the only one most programs use), plane 2 and both planes
+pub fn builtin_themes() -> Vec<Theme> {
+    vec![
+        Theme::new("default", DEFAULT_PALETTE),
+        Theme::new("phosphor", [(0x00, 0x14, 0x00), (0x33, 0xff, 0x33), (0x1a, 0x80, 0x1a), (0xa0, 0xff, 0xa0)]),
+        Theme::new("amber", [(0x1a, 0x0f, 0x00), (0xff, 0xb0, 0x00), (0x80, 0x58, 0x00), (0xff, 0xdc, 0x80)]),
+        Theme::new("lcd", [(0x9b, 0xbc, 0x0f), (0x0f, 0x38, 0x0f), (0x8b, 0xac, 0x0f), (0x30, 0x62, 0x30)]),
+        Theme::new("octo", [(0x99, 0x66, 0x00), (0xff, 0xcc, 0x00), (0xff, 0x66, 0x00), (0x66, 0x22, 0x00)]),
+        Theme::new("contrast", [(0x00, 0x00, 0x00), (0xff, 0xff, 0xff), (0xff, 0xff, 0x00), (0x00, 0xff, 0xff)]),
+    ]
+}
+
+// parse a palette written as hex colours separated by spaces or commas, either the off and on
+// colours or all four colours of the XO-CHIP planes. with only two colours the other planes are
+// shown in the on colour
+pub fn parse_palette(text: &str) -> Option<Palette> {
+    let colours: Option<Vec<(u8, u8, u8)>> = text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|colour| !colour.is_empty())
+        .map(parse_colour)
+        .collect();
+    match colours?.as_slice() {
+        [off, on] => Some([*off, *on, *on, *on]),
+        [off, plane1, plane2, both] => Some([*off, *plane1, *plane2, *both]),
+        _ => None,
+    }
+}
@@ -0,0 +1,24 @@
+// a range of memory to watch for reads, writes or both
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub addr: usize,
+    pub len: usize,
+    pub reads: bool,
+    pub writes: bool,
+}
+
+impl Watchpoint {
+    pub fn hit(&self, addr: usize, write: bool) -> bool {
+        let watched = if write { self.writes } else { self.reads };
+        watched && addr >= self.addr && addr - self.addr < self.len
+    }
+}
+
+// a watched address being accessed by the instruction at pc, value is the byte read or written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchHit {
+    pub pc: usize,
+    pub addr: usize,
+    pub write: bool,
+    pub value: u8,
+}